@@ -1,24 +1,110 @@
+use std::net::IpAddr;
+
 use clap::builder::{PossibleValuesParser, TypedValueParser};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use dns_common::QueryType;
+use dns_common::{Name, QueryType};
 
 #[derive(Parser)]
 pub struct Args {
-    /// The DNS server to query
+    /// The DNS servers to query, tried in order until one answers
     #[clap(short = 's', long = "server", default_value = "8.8.8.8")]
-    pub server: String,
+    pub servers: Vec<String>,
     /// The port to query
     #[clap(short = 'p', long = "port", default_value = "53")]
     pub port: u16,
     /// The name to find the IP address for
     #[clap(short = 'n', long = "name", default_value = "google.com")]
-    pub name: String,
+    pub name: Name,
     #[clap(
     short = 't',
     long = "type",
-    value_parser = PossibleValuesParser::new(["A", "AAAA", "MX", "CNAME", "NS"]).map(| s | s.parse::< QueryType > ().unwrap()),
+    value_parser = PossibleValuesParser::new(["A", "AAAA", "MX", "CNAME", "NS", "SOA", "PTR", "TXT", "SPF", "SRV"]).map(| s | s.parse::< QueryType > ().unwrap()),
     default_value = "A"
     )]
     pub qtype: QueryType,
+    /// Look up the host names of an IP address instead of a name
+    #[clap(short = 'x', long = "reverse", conflicts_with_all = ["name", "qtype"])]
+    pub reverse: Option<IpAddr>,
+    /// Seconds to wait for the first response from a server
+    #[clap(long = "timeout", default_value = "2")]
+    pub timeout: u64,
+    /// How many times to retry a server before trying the next one
+    #[clap(long = "retries", default_value = "2")]
+    pub retries: u32,
+    /// Resolve iteratively from the root servers, printing each delegation step
+    #[clap(long = "trace")]
+    pub trace: bool,
+    /// How to print the response
+    #[clap(long = "format", value_enum, default_value = "text")]
+    pub format: Format,
+    /// dig-style query options: +[no]short, +[no]recurse, +[no]cd, +[no]dnssec,
+    /// +bufsize=N, +[no]question, +[no]answer, +[no]authority, +[no]additional,
+    /// +[no]comments and +[no]stats
+    #[clap(value_name = "+OPTION")]
+    pub query_options: Vec<QueryOption>,
+}
+
+/// The output formats of the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// dig-like presentation format
+    Text,
+    /// The JSON schema of RFC 8427
+    Json,
+    /// The same schema as JSON, in YAML
+    Yaml,
+}
+
+/// A dig-style `+option`, most of which can be turned off with a `no` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryOption {
+    /// Print only the rdata of the answers.
+    Short(bool),
+    Recurse(bool),
+    CheckingDisabled(bool),
+    Dnssec(bool),
+    BufSize(u16),
+    Question(bool),
+    Answer(bool),
+    Authority(bool),
+    Additional(bool),
+    Comments(bool),
+    Stats(bool),
+}
+
+impl std::str::FromStr for QueryOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let option = s
+            .strip_prefix('+')
+            .ok_or_else(|| anyhow::anyhow!("Query options start with +"))?;
+
+        if let Some(size) = option.strip_prefix("bufsize=") {
+            let size = size
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid buffer size {}", size))?;
+            return Ok(QueryOption::BufSize(size));
+        }
+
+        let (enabled, name) = match option.strip_prefix("no") {
+            Some(name) => (false, name),
+            None => (true, option),
+        };
+
+        match name {
+            "short" => Ok(QueryOption::Short(enabled)),
+            "recurse" => Ok(QueryOption::Recurse(enabled)),
+            "cd" | "cdflag" => Ok(QueryOption::CheckingDisabled(enabled)),
+            "dnssec" => Ok(QueryOption::Dnssec(enabled)),
+            "question" => Ok(QueryOption::Question(enabled)),
+            "answer" => Ok(QueryOption::Answer(enabled)),
+            "authority" => Ok(QueryOption::Authority(enabled)),
+            "additional" => Ok(QueryOption::Additional(enabled)),
+            "comments" => Ok(QueryOption::Comments(enabled)),
+            "stats" => Ok(QueryOption::Stats(enabled)),
+            _ => Err(anyhow::anyhow!("Unknown query option {}", s)),
+        }
+    }
 }
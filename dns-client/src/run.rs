@@ -1,28 +1,167 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
 use anyhow::{Context, Result};
-use tokio::net::ToSocketAddrs;
+use tokio::net::{lookup_host, UdpSocket};
+
+use dns_common::{
+    dual_stack_socket, exchange_any, reverse_name, root_servers, traced_recursive_lookup,
+    DisplayOptions, DnsRecord, Exchange, LookupOptions, Name, QueryType,
+};
 
-use dns_common::{lookup, socket};
+use crate::args::{Args, Format, QueryOption};
 
-use crate::args::Args;
+const LOCAL_PORT: u16 = 4310;
 
 pub async fn run(args: Args) -> Result<()> {
     let Args {
-        server,
+        servers,
         port,
         name,
         qtype,
+        reverse,
+        timeout,
+        retries,
+        trace,
+        format,
+        query_options,
     } = args;
 
-    let socket = socket(addr()).await?;
-    let dns_server = format!("{}:{}", server, port);
-    let response = lookup(&socket, dns_server, &name, &qtype)
+    let (name, qtype) = match reverse {
+        Some(ip) => (reverse_name(ip), QueryType::PTR),
+        None => (name, qtype),
+    };
+
+    let mut options = LookupOptions {
+        timeout: Duration::from_secs(timeout),
+        retries,
+        recursion_desired: true,
+        ..LookupOptions::default()
+    };
+    let mut display = DisplayOptions::default();
+    let mut short = false;
+    let mut stats = true;
+
+    // Later options override earlier ones, as with dig.
+    for option in query_options {
+        match option {
+            QueryOption::Short(enabled) => short = enabled,
+            QueryOption::Recurse(enabled) => options.recursion_desired = enabled,
+            QueryOption::CheckingDisabled(enabled) => options.checking_disabled = enabled,
+            QueryOption::Dnssec(enabled) => options.dnssec_ok = enabled,
+            QueryOption::BufSize(size) => options.payload_size = size,
+            QueryOption::Question(enabled) => display.question = enabled,
+            QueryOption::Answer(enabled) => display.answer = enabled,
+            QueryOption::Authority(enabled) => display.authority = enabled,
+            QueryOption::Additional(enabled) => display.additional = enabled,
+            QueryOption::Comments(enabled) => display.comments = enabled,
+            QueryOption::Stats(enabled) => stats = enabled,
+        }
+    }
+
+    let socket = dual_stack_socket(LOCAL_PORT).await?;
+    if trace {
+        return run_trace(&socket, &name, qtype, &options).await;
+    }
+
+    let dns_servers = resolve_servers(&servers, port).await?;
+    let exchange = exchange_any(&socket, &dns_servers, &name, &qtype, &options)
         .await
         .context("Failed to lookup")?;
 
-    println!("{:#?}", response);
+    match format {
+        Format::Text => {}
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(&exchange.response)?);
+            return Ok(());
+        }
+        Format::Yaml => {
+            print!("{}", serde_yaml::to_string(&exchange.response)?);
+            return Ok(());
+        }
+    }
+
+    // Reverse lookups print just the host names, as they did before the
+    // dig-style output existed.
+    if reverse.is_some() {
+        for rec in &exchange.response.answers {
+            if let DnsRecord::PTR { host, .. } = rec {
+                println!("{}", host);
+            }
+        }
+        return Ok(());
+    }
+
+    if short {
+        for rec in &exchange.response.answers {
+            println!("{}", rec.rdata());
+        }
+        return Ok(());
+    }
+
+    print!("{}", exchange.response.display(&display));
+    if stats {
+        print_stats(&exchange);
+    }
+
     Ok(())
 }
 
-fn addr() -> impl ToSocketAddrs {
-    "0.0.0.0:4310".to_string()
+/// Resolve `name` from the root servers and print the records each server
+/// returned, the way dig +trace does.
+async fn run_trace(
+    socket: &UdpSocket,
+    name: &Name,
+    qtype: QueryType,
+    options: &LookupOptions,
+) -> Result<()> {
+    let mut trace = Vec::new();
+    let result =
+        traced_recursive_lookup(socket, &root_servers(), name, qtype, options, &mut trace).await;
+
+    for step in &trace {
+        let response = &step.exchange.response;
+        for rec in response.answers.iter().chain(&response.authorities) {
+            println!("{}", rec);
+        }
+        println!(
+            ";; Received {} bytes from {}#{}({}) in {} ms",
+            step.exchange.size,
+            step.exchange.server.ip(),
+            step.exchange.server.port(),
+            step.exchange.server.ip(),
+            step.exchange.elapsed.as_millis()
+        );
+        println!();
+    }
+
+    result.context("Failed to lookup")?;
+    Ok(())
+}
+
+/// Print the footer dig shows below a response.
+fn print_stats(exchange: &Exchange) {
+    println!();
+    println!(";; Query time: {} msec", exchange.elapsed.as_millis());
+    println!(
+        ";; SERVER: {}#{}({}) ({})",
+        exchange.server.ip(),
+        exchange.server.port(),
+        exchange.server.ip(),
+        if exchange.tcp { "TCP" } else { "UDP" }
+    );
+    println!(";; MSG SIZE  rcvd: {}", exchange.size);
+}
+
+async fn resolve_servers(servers: &[String], port: u16) -> Result<Vec<SocketAddr>> {
+    let mut dns_servers = vec![];
+
+    for server in servers {
+        let addrs = lookup_host(format!("{}:{}", server, port))
+            .await
+            .with_context(|| format!("Failed to resolve server {}", server))?;
+        dns_servers.extend(addrs);
+    }
+
+    Ok(dns_servers)
 }
@@ -1,24 +1,76 @@
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Result};
 
+use crate::Name;
+
+/// Maximum size of a DNS message over plain UDP (RFC 1035 section 4.2.1).
+pub const UDP_MESSAGE_SIZE: usize = 512;
+
+/// Maximum size of any DNS message, bounded by the TCP length prefix.
+pub const MAX_MESSAGE_SIZE: usize = 65535;
+
+/// Offsets above this cannot be encoded in a 14-bit compression pointer.
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
 pub struct BytePacketBuffer {
-    pub buffer: [u8; 512],
+    pub buffer: Vec<u8>,
     pub position: usize,
+    max_size: usize,
+    compression: bool,
+    labels: HashMap<Name, usize>,
 }
 
 impl BytePacketBuffer {
+    /// Create an empty buffer for a classic 512 byte UDP message.
     pub fn new() -> BytePacketBuffer {
+        BytePacketBuffer::with_max_size(UDP_MESSAGE_SIZE)
+    }
+
+    /// Create an empty buffer that grows up to `max_size` bytes while writing.
+    ///
+    /// Use the EDNS advertised payload size for UDP responses and
+    /// `MAX_MESSAGE_SIZE` for TCP.
+    pub fn with_max_size(max_size: usize) -> BytePacketBuffer {
         BytePacketBuffer {
-            buffer: [0; 512],
+            buffer: Vec::new(),
             position: 0,
+            max_size: max_size.min(MAX_MESSAGE_SIZE),
+            compression: true,
+            labels: HashMap::new(),
         }
     }
 
+    /// Create a buffer for reading a message that was received from the wire.
+    pub fn from_bytes(bytes: &[u8]) -> BytePacketBuffer {
+        let mut buffer = BytePacketBuffer::with_max_size(MAX_MESSAGE_SIZE);
+        buffer.buffer.extend_from_slice(bytes);
+        buffer
+    }
+
+    /// The bytes written to the buffer so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.position]
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Enable or disable name compression for subsequent writes.
+    ///
+    /// Compression is on by default. Disable it when the canonical wire
+    /// form is needed, e.g. for DNSSEC signing and validation.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
     pub fn position(&self) -> usize {
         self.position
     }
 
     fn read(&mut self) -> Result<u8> {
-        if self.position >= 512 {
+        if self.position >= self.buffer.len() {
             return Err(anyhow!("End of buffer"));
         }
 
@@ -34,7 +86,7 @@ impl BytePacketBuffer {
     }
 
     fn get(&self, position: usize) -> Result<u8> {
-        if position >= 512 {
+        if position >= self.buffer.len() {
             return Err(anyhow!("End of buffer"));
         }
 
@@ -42,12 +94,22 @@ impl BytePacketBuffer {
     }
 
     pub fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
-        if start + len >= 512 {
+        if start + len > self.buffer.len() {
             return Err(anyhow!("End of buffer"));
         }
         Ok(&self.buffer[start..start + len])
     }
 
+    pub fn read_u8(&mut self) -> Result<u8> {
+        self.read()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let bytes = self.get_range(self.position, len)?.to_vec();
+        self.position += len;
+        Ok(bytes)
+    }
+
     pub fn read_u16(&mut self) -> Result<u16> {
         let result = ((self.read()? as u16) << 8) | (self.read()? as u16);
         Ok(result)
@@ -65,14 +127,15 @@ impl BytePacketBuffer {
     /// Read a qname
     ///
     /// The tricky part: Reading domain names, taking labels into consideration.
-    /// Will take something like [3]www[6]google[3]com[0] and append
-    /// www.google.com to outstr.
-    pub fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
+    /// Will take something like [3]www[6]google[3]com[0], following
+    /// compression pointers, and return the name www.google.com with the
+    /// case of its labels preserved.
+    pub fn read_qname(&mut self) -> Result<Name> {
         let mut pos = self.position();
         let mut jumped = false;
         let max_jumps = 5;
         let mut jumps_performed = 0;
-        let mut delim = "";
+        let mut labels = Vec::new();
 
         loop {
             if jumps_performed > max_jumps {
@@ -95,17 +158,15 @@ impl BytePacketBuffer {
                 jumped = true;
                 jumps_performed += 1;
                 continue;
+            } else if (len & 0xC0) != 0 {
+                return Err(anyhow!("Unsupported label type {:#04x}", len & 0xC0));
             } else {
                 pos += 1;
                 if len == 0 {
                     break;
                 }
 
-                outstr.push_str(delim);
-
-                let str_buffer = self.get_range(pos, len as usize)?;
-                outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
-                delim = ".";
+                labels.push(self.get_range(pos, len as usize)?.to_vec());
                 pos += len as usize;
             }
         }
@@ -114,15 +175,20 @@ impl BytePacketBuffer {
             self.seek(pos)?;
         }
 
-        Ok(())
+        Name::from_labels(labels)
     }
 
     fn write(&mut self, val: u8) -> Result<()> {
-        if self.position >= 512 {
+        if self.position >= self.max_size {
             return Err(anyhow!("End of buffer"));
         }
 
-        self.buffer[self.position] = val;
+        if self.position < self.buffer.len() {
+            self.buffer[self.position] = val;
+        } else {
+            self.buffer.resize(self.position, 0);
+            self.buffer.push(val);
+        }
         self.position += 1;
         Ok(())
     }
@@ -131,6 +197,13 @@ impl BytePacketBuffer {
         self.write(val)
     }
 
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        for b in bytes {
+            self.write(*b)?;
+        }
+        Ok(())
+    }
+
     pub fn write_u16(&mut self, val: u16) -> Result<()> {
         self.write((val >> 8) as u8)?;
         self.write((val & 0xFF) as u8)
@@ -143,17 +216,38 @@ impl BytePacketBuffer {
         self.write((val & 0xFF) as u8)
     }
 
-    pub fn write_qname(&mut self, qname: &str) -> Result<()> {
-        for label in qname.split('.') {
-            let len = label.len();
-            if len > 0x3f {
-                return Err(anyhow!("Single label exceeds 63 characters of length"));
+    /// Write a qname
+    ///
+    /// Suffixes that were already written to the buffer are replaced by a
+    /// compression pointer (RFC 1035 section 4.1.4), unless compression has
+    /// been disabled with `set_compression`.
+    pub fn write_qname(&mut self, qname: &Name) -> Result<()> {
+        self.write_labels(qname, self.compression)
+    }
+
+    /// Write a qname without compressing it, regardless of the buffer setting.
+    ///
+    /// Needed for rdata of record types that must not be compressed, such
+    /// as the SRV target (RFC 2782). The written suffixes can still be
+    /// pointed to by later names.
+    pub fn write_uncompressed_qname(&mut self, qname: &Name) -> Result<()> {
+        self.write_labels(qname, false)
+    }
+
+    fn write_labels(&mut self, qname: &Name, compress: bool) -> Result<()> {
+        for (label, suffix) in qname.labels().zip(qname.ancestors()) {
+            if compress {
+                if let Some(&offset) = self.labels.get(&suffix) {
+                    return self.write_u16(0xC000 | offset as u16);
+                }
             }
 
-            self.write_u8(len as u8)?;
-            for b in label.as_bytes() {
-                self.write_u8(*b)?;
+            if self.position <= MAX_POINTER_OFFSET {
+                self.labels.entry(suffix).or_insert(self.position);
             }
+
+            self.write_u8(label.len() as u8)?;
+            self.write_bytes(label)?;
         }
 
         self.write_u8(0)?;
@@ -162,6 +256,10 @@ impl BytePacketBuffer {
     }
 
     fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+        if pos >= self.buffer.len() {
+            return Err(anyhow!("End of buffer"));
+        }
+
         self.buffer[pos] = val;
         Ok(())
     }
@@ -171,3 +269,137 @@ impl BytePacketBuffer {
         self.set(pos + 1, (val & 0xFF) as u8)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{DnsClass, DnsPacket, DnsQuestion, DnsRecord, QueryType};
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn write_qname_points_to_written_suffixes() {
+        let mut buffer = BytePacketBuffer::new();
+        buffer.write_qname(&name("www.example.com")).unwrap();
+        buffer.write_qname(&name("mail.example.com")).unwrap();
+        buffer.write_qname(&name("www.example.com")).unwrap();
+
+        // example.com starts at offset 4, www.example.com at 0.
+        assert_eq!(&buffer.as_bytes()[17..], b"\x04mail\xc0\x04\xc0\x00");
+
+        let mut reader = BytePacketBuffer::from_bytes(buffer.as_bytes());
+        for expected in ["www.example.com", "mail.example.com", "www.example.com"] {
+            assert_eq!(reader.read_qname().unwrap(), name(expected));
+        }
+        assert_eq!(reader.position(), buffer.as_bytes().len());
+    }
+
+    #[test]
+    fn packet_with_shared_names_round_trips() {
+        let a = |domain: &str, address: Ipv4Addr| DnsRecord::A {
+            domain: name(domain),
+            address,
+            class: DnsClass::IN,
+            ttl: 300,
+        };
+
+        let mut packet = DnsPacket::new();
+        packet
+            .questions
+            .push(DnsQuestion::new(name("www.example.com"), QueryType::A));
+        packet.answers.push(DnsRecord::CNAME {
+            domain: name("www.example.com"),
+            alias: name("web.example.com"),
+            class: DnsClass::IN,
+            ttl: 300,
+        });
+        packet
+            .answers
+            .push(a("web.example.com", Ipv4Addr::new(192, 0, 2, 1)));
+        packet.authorities.push(DnsRecord::NS {
+            domain: name("example.com"),
+            name_server: name("ns1.example.com"),
+            class: DnsClass::IN,
+            ttl: 300,
+        });
+        packet
+            .resources
+            .push(a("ns1.example.com", Ipv4Addr::new(192, 0, 2, 53)));
+
+        let mut compressed = BytePacketBuffer::new();
+        packet.write(&mut compressed).unwrap();
+        let mut uncompressed = BytePacketBuffer::new();
+        uncompressed.set_compression(false);
+        packet.write(&mut uncompressed).unwrap();
+        assert!(compressed.as_bytes().len() < uncompressed.as_bytes().len());
+
+        for buffer in [compressed, uncompressed] {
+            let read = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(buffer.as_bytes()))
+                .unwrap();
+            assert_eq!(read.questions, packet.questions);
+            assert_eq!(read.answers, packet.answers);
+            assert_eq!(read.authorities, packet.authorities);
+            assert_eq!(read.resources, packet.resources);
+        }
+    }
+
+    #[test]
+    fn srv_target_is_never_compressed() {
+        let target = name("sip.example.com");
+        let srv = DnsRecord::SRV {
+            domain: name("_sip._udp.example.com"),
+            priority: 10,
+            weight: 5,
+            port: 5060,
+            target: target.clone(),
+            class: DnsClass::IN,
+            ttl: 300,
+        };
+
+        let mut buffer = BytePacketBuffer::new();
+        buffer.write_qname(&target).unwrap();
+        srv.write(&mut buffer).unwrap();
+
+        // The owner name still points to example.com, but the target is
+        // spelled out even though it was written just before.
+        let bytes = buffer.as_bytes();
+        assert_eq!(&bytes[17..29], b"\x04_sip\x04_udp\xc0\x04");
+        assert!(bytes.ends_with(b"\x03sip\x07example\x03com\x00"));
+
+        let mut reader = BytePacketBuffer::from_bytes(bytes);
+        reader.read_qname().unwrap();
+        assert_eq!(DnsRecord::read(&mut reader).unwrap(), srv);
+    }
+
+    #[test]
+    fn read_qname_follows_at_most_five_jumps() {
+        // The root name at offset 0, then six pointers, each to the one before.
+        let mut bytes = vec![0];
+        for i in 0..6u8 {
+            let target = if i == 0 { 0 } else { 1 + 2 * (i - 1) };
+            bytes.extend([0xC0, target]);
+        }
+
+        let mut five_jumps = BytePacketBuffer::from_bytes(&bytes);
+        five_jumps.seek(9).unwrap();
+        assert_eq!(five_jumps.read_qname().unwrap(), Name::root());
+        assert_eq!(five_jumps.position(), 11);
+
+        let mut six_jumps = BytePacketBuffer::from_bytes(&bytes);
+        six_jumps.seek(11).unwrap();
+        assert!(six_jumps.read_qname().is_err());
+    }
+
+    #[test]
+    fn read_qname_rejects_pointer_loops() {
+        for bytes in [&b"\xc0\x00"[..], &b"\x03www\xc0\x06\xc0\x00"[..]] {
+            let mut buffer = BytePacketBuffer::from_bytes(bytes);
+            let err = buffer.read_qname().unwrap_err();
+            assert_eq!(err.to_string(), "too many jumps");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+/// The class of a question or record (RFC 1035 section 3.2.4).
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum DnsClass {
+    Unknown(u16),
+    #[default]
+    IN,
+    CH,
+    HS,
+    NONE,
+    ANY,
+}
+
+impl DnsClass {
+    pub fn from_u16(value: u16) -> DnsClass {
+        match value {
+            1 => DnsClass::IN,
+            3 => DnsClass::CH,
+            4 => DnsClass::HS,
+            254 => DnsClass::NONE,
+            255 => DnsClass::ANY,
+            _ => DnsClass::Unknown(value),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            DnsClass::IN => 1,
+            DnsClass::CH => 3,
+            DnsClass::HS => 4,
+            DnsClass::NONE => 254,
+            DnsClass::ANY => 255,
+            DnsClass::Unknown(value) => value,
+        }
+    }
+}
+
+impl std::fmt::Display for DnsClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DnsClass::Unknown(value) => write!(f, "CLASS{}", value),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl std::str::FromStr for DnsClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "IN" => Ok(DnsClass::IN),
+            "CH" => Ok(DnsClass::CH),
+            "HS" => Ok(DnsClass::HS),
+            "NONE" => Ok(DnsClass::NONE),
+            "ANY" => Ok(DnsClass::ANY),
+            // The generic CLASSnnn form of RFC 3597 section 5.
+            _ => match s.strip_prefix("CLASS").and_then(|n| n.parse().ok()) {
+                Some(value) => Ok(DnsClass::from_u16(value)),
+                None => Err(anyhow::anyhow!("Unknown class")),
+            },
+        }
+    }
+}
@@ -83,7 +83,7 @@ impl DnsHeader {
         )?;
 
         buffer.write_u8(
-            (self.result_code as u8)
+            ((self.result_code.to_u16() & 0x0F) as u8)
                 | ((self.checking_disabled as u8) << 4)
                 | ((self.authenticated_data as u8) << 5)
                 | ((self.z as u8) << 6)
@@ -1,8 +1,11 @@
-use std::net::Ipv4Addr;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use crate::{BytePacketBuffer, DnsHeader, DnsQuestion, DnsRecord, QueryType};
+use crate::{
+    BytePacketBuffer, DnsHeader, DnsQuestion, DnsRecord, Edns, Name, QueryType, ResultCode,
+};
 
 #[derive(Debug, Clone)]
 pub struct DnsPacket {
@@ -11,6 +14,8 @@ pub struct DnsPacket {
     pub answers: Vec<DnsRecord>,
     pub authorities: Vec<DnsRecord>,
     pub resources: Vec<DnsRecord>,
+    /// The OPT pseudo-record, kept out of `resources`.
+    pub edns: Option<Edns>,
 }
 
 impl DnsPacket {
@@ -21,6 +26,7 @@ impl DnsPacket {
             answers: Vec::new(),
             authorities: Vec::new(),
             resources: Vec::new(),
+            edns: None,
         }
     }
 
@@ -30,7 +36,7 @@ impl DnsPacket {
         result.header.read(buffer)?;
 
         for _ in 0..result.header.question_count {
-            let mut q = DnsQuestion::new("".to_string(), QueryType::Unknown(0));
+            let mut q = DnsQuestion::new(Name::root(), QueryType::Unknown(0));
             q.read(buffer)?;
             result.questions.push(q);
         }
@@ -46,8 +52,19 @@ impl DnsPacket {
         }
 
         for _ in 0..result.header.resource_entry_count {
-            let r = DnsRecord::read(buffer)?;
-            result.resources.push(r);
+            match DnsRecord::read(buffer)? {
+                DnsRecord::OPT { edns } => {
+                    if result.edns.is_some() {
+                        return Err(anyhow!("More than one OPT record"));
+                    }
+
+                    let rcode =
+                        ((edns.extended_rcode as u16) << 4) | result.header.result_code.to_u16();
+                    result.header.result_code = ResultCode::from_u16(rcode);
+                    result.edns = Some(edns);
+                }
+                r => result.resources.push(r),
+            }
         }
 
         Ok(result)
@@ -57,7 +74,12 @@ impl DnsPacket {
         self.header.question_count = self.questions.len() as u16;
         self.header.answer_count = self.answers.len() as u16;
         self.header.authoritative_entry_count = self.authorities.len() as u16;
-        self.header.resource_entry_count = self.resources.len() as u16;
+        self.header.resource_entry_count =
+            (self.resources.len() + self.edns.is_some() as usize) as u16;
+
+        if let Some(edns) = self.edns.as_mut() {
+            edns.extended_rcode = (self.header.result_code.to_u16() >> 4) as u8;
+        }
 
         self.header.write(buffer)?;
 
@@ -73,10 +95,58 @@ impl DnsPacket {
         for rec in &self.resources {
             rec.write(buffer)?;
         }
+        if let Some(edns) = &self.edns {
+            edns.write(buffer)?;
+        }
 
         Ok(())
     }
 
+    /// Whether this packet answers `query`: the response flag is set, the IDs
+    /// agree and the question sections are identical, up to the case of names.
+    pub fn is_response_to(&self, query: &DnsPacket) -> bool {
+        self.header.response
+            && self.header.id == query.header.id
+            && self.questions == query.questions
+    }
+
+    /// The target of the CNAME for `name` in the answer section, if any.
+    pub fn get_cname(&self, name: &Name) -> Option<&Name> {
+        self.answers.iter().find_map(|r| match r {
+            DnsRecord::CNAME { domain, alias, .. } if domain == name => Some(alias),
+            _ => None,
+        })
+    }
+
+    /// Drop every record whose owner is outside `zone`.
+    ///
+    /// A server authoritative for `zone` has no business telling us about other
+    /// names, so such records are discarded before they can be cached or
+    /// returned. This also rejects out-of-bailiwick glue.
+    pub fn strip_out_of_zone(&mut self, zone: &Name) {
+        for section in [
+            &mut self.answers,
+            &mut self.authorities,
+            &mut self.resources,
+        ] {
+            section.retain(|r| r.domain().is_subdomain_of(zone));
+        }
+    }
+
+    /// The deepest zone the authority section delegates `qname` to, if any.
+    pub fn get_referral_zone<'a>(&'a self, qname: &'a Name) -> Option<&'a Name> {
+        qname.closest_zone(self.get_ns(qname).map(|(domain, _)| domain))
+    }
+
+    /// How long a negative answer may be cached: the lesser of the TTL and the
+    /// minimum field of the SOA in the authority section (RFC 2308 section 5).
+    pub fn negative_ttl(&self) -> Option<u32> {
+        self.authorities.iter().find_map(|r| match *r {
+            DnsRecord::SOA { minimum, ttl, .. } => Some(ttl.min(minimum)),
+            _ => None,
+        })
+    }
+
     pub fn get_random_a(&self) -> Option<Ipv4Addr> {
         self.answers
             .iter()
@@ -87,7 +157,17 @@ impl DnsPacket {
             .next()
     }
 
-    fn get_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+    pub fn get_random_aaaa(&self) -> Option<Ipv6Addr> {
+        self.answers
+            .iter()
+            .filter_map(|r| match r {
+                DnsRecord::AAAA { address, .. } => Some(*address),
+                _ => None,
+            })
+            .next()
+    }
+
+    fn get_ns<'a>(&'a self, qname: &'a Name) -> impl Iterator<Item = (&'a Name, &'a Name)> {
         self.authorities
             .iter()
             .filter_map(|r| match r {
@@ -95,27 +175,178 @@ impl DnsPacket {
                     domain,
                     name_server,
                     ..
-                } => Some((domain.as_str(), name_server.as_str())),
+                } => Some((domain, name_server)),
                 _ => None,
             })
-            .filter(move |(domain, _)| qname.ends_with(*domain))
+            .filter(move |(domain, _)| qname.is_subdomain_of(domain))
     }
 
-    pub fn get_resolved_ns(&self, qname: &str) -> Option<Ipv4Addr> {
+    fn get_referral_ns<'a>(
+        &'a self,
+        qname: &'a Name,
+    ) -> impl Iterator<Item = (&'a Name, &'a Name)> {
+        let zone = self.get_referral_zone(qname);
         self.get_ns(qname)
+            .filter(move |(domain, _)| Some(*domain) == zone)
+    }
+
+    /// The glue addresses of the name servers for the deepest zone delegated to.
+    pub fn get_resolved_ns(&self, qname: &Name) -> Vec<IpAddr> {
+        self.get_referral_ns(qname)
             .flat_map(|(_, host)| {
                 self.resources.iter().filter_map(move |r| match r {
                     DnsRecord::A {
                         domain, address, ..
-                    } if domain == host => Some(address),
+                    } if domain == host => Some(IpAddr::V4(*address)),
+                    DnsRecord::AAAA {
+                        domain, address, ..
+                    } if domain == host => Some(IpAddr::V6(*address)),
                     _ => None,
                 })
             })
-            .copied()
-            .next()
+            .collect()
     }
 
-    pub fn get_unresolved_ns<'a>(&'a self, qname: &'a str) -> Option<&'a str> {
-        self.get_ns(qname).map(|(_, host)| host).next()
+    pub fn get_unresolved_ns<'a>(&'a self, qname: &'a Name) -> Option<&'a Name> {
+        self.get_referral_ns(qname).map(|(_, host)| host).next()
+    }
+}
+
+/// Which parts of a packet its dig-like display shows.
+#[derive(Debug, Clone)]
+pub struct DisplayOptions {
+    /// The header, the OPT pseudo-section and the section titles.
+    pub comments: bool,
+    pub question: bool,
+    pub answer: bool,
+    pub authority: bool,
+    pub additional: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            comments: true,
+            question: true,
+            answer: true,
+            authority: true,
+            additional: true,
+        }
+    }
+}
+
+/// A packet shown with a choice of `DisplayOptions`, see `DnsPacket::display`.
+pub struct PacketDisplay<'a> {
+    packet: &'a DnsPacket,
+    options: &'a DisplayOptions,
+}
+
+impl DnsPacket {
+    /// Show only the parts of the packet selected by `options`.
+    pub fn display<'a>(&'a self, options: &'a DisplayOptions) -> PacketDisplay<'a> {
+        PacketDisplay {
+            packet: self,
+            options,
+        }
+    }
+}
+
+impl fmt::Display for DnsPacket {
+    /// Write the packet the way dig shows it: header, flags, counts and sections.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(&DisplayOptions::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for PacketDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.packet;
+        let options = self.options;
+        let header = &packet.header;
+
+        // Blocks are separated by blank lines.
+        let mut first = true;
+        let mut block = |f: &mut fmt::Formatter<'_>| -> fmt::Result {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            Ok(())
+        };
+
+        if options.comments {
+            block(f)?;
+            let opcode = match header.opcode {
+                0 => "QUERY".to_string(),
+                1 => "IQUERY".to_string(),
+                2 => "STATUS".to_string(),
+                4 => "NOTIFY".to_string(),
+                5 => "UPDATE".to_string(),
+                other => other.to_string(),
+            };
+            writeln!(
+                f,
+                ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+                opcode, header.result_code, header.id
+            )?;
+
+            let flags: Vec<&str> = [
+                (header.response, "qr"),
+                (header.authoritative_answer, "aa"),
+                (header.truncated_message, "tc"),
+                (header.recursion_desired, "rd"),
+                (header.recursion_available, "ra"),
+                (header.authenticated_data, "ad"),
+                (header.checking_disabled, "cd"),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| *flag)
+            .collect();
+            writeln!(
+                f,
+                ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+                flags.join(" "),
+                packet.questions.len(),
+                packet.answers.len(),
+                packet.authorities.len(),
+                packet.resources.len() + packet.edns.is_some() as usize
+            )?;
+
+            if let Some(edns) = &packet.edns {
+                block(f)?;
+                writeln!(f, ";; OPT PSEUDOSECTION:\n{}", edns)?;
+            }
+        }
+
+        if options.question {
+            block(f)?;
+            if options.comments {
+                writeln!(f, ";; QUESTION SECTION:")?;
+            }
+            for question in &packet.questions {
+                writeln!(f, ";{}", question)?;
+            }
+        }
+
+        for (shown, title, records) in [
+            (options.answer, "ANSWER", &packet.answers),
+            (options.authority, "AUTHORITY", &packet.authorities),
+            (options.additional, "ADDITIONAL", &packet.resources),
+        ] {
+            if !shown || records.is_empty() {
+                continue;
+            }
+
+            block(f)?;
+            if options.comments {
+                writeln!(f, ";; {} SECTION:", title)?;
+            }
+            for rec in records {
+                writeln!(f, "{}", rec)?;
+            }
+        }
+
+        Ok(())
     }
 }
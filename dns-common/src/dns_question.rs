@@ -1,27 +1,46 @@
-use crate::{BytePacketBuffer, QueryType};
+use std::fmt;
+
+use crate::{BytePacketBuffer, DnsClass, Name, QueryType};
 use anyhow::Result;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DnsQuestion {
-    pub qname: String,
+    pub qname: Name,
     pub qtype: QueryType,
+    pub qclass: DnsClass,
 }
 
 impl DnsQuestion {
-    pub fn new(qname: String, qtype: QueryType) -> Self {
-        DnsQuestion { qname, qtype }
+    /// A question in the Internet class.
+    pub fn new(qname: Name, qtype: QueryType) -> Self {
+        DnsQuestion::with_class(qname, qtype, DnsClass::IN)
+    }
+
+    pub fn with_class(qname: Name, qtype: QueryType, qclass: DnsClass) -> Self {
+        DnsQuestion {
+            qname,
+            qtype,
+            qclass,
+        }
     }
 
     pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
-        buffer.read_qname(&mut self.qname)?;
+        self.qname = buffer.read_qname()?;
         self.qtype = QueryType::from_u16(buffer.read_u16()?);
-        buffer.read_u16()?;
+        self.qclass = DnsClass::from_u16(buffer.read_u16()?);
         Ok(())
     }
 
     pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<()> {
         buffer.write_qname(&self.qname)?;
         buffer.write_u16(self.qtype.to_u16())?;
-        buffer.write_u16(1)
+        buffer.write_u16(self.qclass.to_u16())
+    }
+}
+
+impl fmt::Display for DnsQuestion {
+    /// Write the question as `name class type`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t{}", self.qname, self.qclass, self.qtype)
     }
 }
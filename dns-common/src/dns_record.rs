@@ -1,57 +1,399 @@
+use std::fmt;
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 
-use crate::{BytePacketBuffer, QueryType};
+use crate::presentation::{
+    parse_character_string, parse_hex, parse_ttl, tokenize, write_character_string,
+};
+use crate::{BytePacketBuffer, DnsClass, Edns, Name, QueryType, MAX_MESSAGE_SIZE};
+
+/// The owner name of the OPT pseudo-record.
+static ROOT: Name = Name::root();
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[allow(dead_code)]
 pub enum DnsRecord {
+    /// A record of a type we do not know, kept as opaque rdata (RFC 3597).
     UNKNOWN {
-        domain: String,
+        domain: Name,
         qtype: u16,
-        data_len: u16,
+        class: DnsClass,
+        data: Vec<u8>,
         ttl: u32,
     },
     A {
-        domain: String,
+        domain: Name,
         address: Ipv4Addr,
+        class: DnsClass,
         ttl: u32,
     },
     NS {
-        domain: String,
-        name_server: String,
+        domain: Name,
+        name_server: Name,
+        class: DnsClass,
         ttl: u32,
     },
     CNAME {
-        domain: String,
-        alias: String,
+        domain: Name,
+        alias: Name,
+        class: DnsClass,
+        ttl: u32,
+    },
+    PTR {
+        domain: Name,
+        host: Name,
+        class: DnsClass,
         ttl: u32,
     },
     MX {
-        domain: String,
+        domain: Name,
         preference: u16,
-        host: String,
+        host: Name,
+        class: DnsClass,
         ttl: u32,
     },
     AAAA {
-        domain: String,
+        domain: Name,
         address: Ipv6Addr,
+        class: DnsClass,
+        ttl: u32,
+    },
+    SOA {
+        domain: Name,
+        mname: Name,
+        rname: Name,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+        class: DnsClass,
+        ttl: u32,
+    },
+    TXT {
+        domain: Name,
+        data: Vec<Vec<u8>>,
+        class: DnsClass,
         ttl: u32,
     },
+    SPF {
+        domain: Name,
+        data: Vec<Vec<u8>>,
+        class: DnsClass,
+        ttl: u32,
+    },
+    SRV {
+        domain: Name,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+        class: DnsClass,
+        ttl: u32,
+    },
+    OPT {
+        edns: Edns,
+    },
 }
 
 impl DnsRecord {
+    /// The owner name of the record; the root for the OPT pseudo-record.
+    pub fn domain(&self) -> &Name {
+        match self {
+            DnsRecord::UNKNOWN { domain, .. }
+            | DnsRecord::A { domain, .. }
+            | DnsRecord::NS { domain, .. }
+            | DnsRecord::CNAME { domain, .. }
+            | DnsRecord::MX { domain, .. }
+            | DnsRecord::AAAA { domain, .. }
+            | DnsRecord::SOA { domain, .. }
+            | DnsRecord::TXT { domain, .. }
+            | DnsRecord::SPF { domain, .. }
+            | DnsRecord::SRV { domain, .. }
+            | DnsRecord::PTR { domain, .. } => domain,
+            DnsRecord::OPT { .. } => &ROOT,
+        }
+    }
+
+    pub fn query_type(&self) -> QueryType {
+        match *self {
+            DnsRecord::UNKNOWN { qtype, .. } => QueryType::from_u16(qtype),
+            DnsRecord::A { .. } => QueryType::A,
+            DnsRecord::NS { .. } => QueryType::NS,
+            DnsRecord::CNAME { .. } => QueryType::CNAME,
+            DnsRecord::MX { .. } => QueryType::MX,
+            DnsRecord::AAAA { .. } => QueryType::AAAA,
+            DnsRecord::SOA { .. } => QueryType::SOA,
+            DnsRecord::TXT { .. } => QueryType::TXT,
+            DnsRecord::SPF { .. } => QueryType::SPF,
+            DnsRecord::SRV { .. } => QueryType::SRV,
+            DnsRecord::PTR { .. } => QueryType::PTR,
+            DnsRecord::OPT { .. } => QueryType::OPT,
+        }
+    }
+
+    /// The class of the record; the OPT pseudo-record reuses the field for its payload size.
+    pub fn class(&self) -> Option<DnsClass> {
+        match *self {
+            DnsRecord::UNKNOWN { class, .. }
+            | DnsRecord::A { class, .. }
+            | DnsRecord::NS { class, .. }
+            | DnsRecord::CNAME { class, .. }
+            | DnsRecord::MX { class, .. }
+            | DnsRecord::AAAA { class, .. }
+            | DnsRecord::SOA { class, .. }
+            | DnsRecord::TXT { class, .. }
+            | DnsRecord::SPF { class, .. }
+            | DnsRecord::SRV { class, .. }
+            | DnsRecord::PTR { class, .. } => Some(class),
+            DnsRecord::OPT { .. } => None,
+        }
+    }
+
+    /// Show just the rdata of the record in presentation format, as dig +short does.
+    pub fn rdata(&self) -> RdataDisplay<'_> {
+        RdataDisplay(self)
+    }
+
+    /// The time to live of the record; zero for the OPT pseudo-record.
+    pub fn ttl(&self) -> u32 {
+        match *self {
+            DnsRecord::UNKNOWN { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::SPF { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::PTR { ttl, .. } => ttl,
+            DnsRecord::OPT { .. } => 0,
+        }
+    }
+
+    pub fn set_ttl(&mut self, new_ttl: u32) {
+        match self {
+            DnsRecord::UNKNOWN { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::SPF { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::PTR { ttl, .. } => *ttl = new_ttl,
+            DnsRecord::OPT { .. } => {}
+        }
+    }
+
+    /// Change the owner name of the record, e.g. when synthesizing it from a
+    /// wildcard. The OPT pseudo-record always belongs to the root.
+    pub fn set_domain(&mut self, new_domain: Name) {
+        match self {
+            DnsRecord::UNKNOWN { domain, .. }
+            | DnsRecord::A { domain, .. }
+            | DnsRecord::NS { domain, .. }
+            | DnsRecord::CNAME { domain, .. }
+            | DnsRecord::MX { domain, .. }
+            | DnsRecord::AAAA { domain, .. }
+            | DnsRecord::SOA { domain, .. }
+            | DnsRecord::TXT { domain, .. }
+            | DnsRecord::SPF { domain, .. }
+            | DnsRecord::SRV { domain, .. }
+            | DnsRecord::PTR { domain, .. } => *domain = new_domain,
+            DnsRecord::OPT { .. } => {}
+        }
+    }
+
+    /// Build a record from the presentation format fields of its rdata, in
+    /// which names without a trailing dot are relative to `origin`.
+    ///
+    /// Any type may use the generic `\# <length> <hex>` form of RFC 3597.
+    pub fn from_rdata(
+        domain: Name,
+        qtype: QueryType,
+        class: DnsClass,
+        ttl: u32,
+        rdata: &[String],
+        origin: &Name,
+    ) -> Result<DnsRecord> {
+        if rdata.first().is_some_and(|field| field == "\\#") {
+            let len: usize = rdata
+                .get(1)
+                .ok_or_else(|| anyhow!("Missing rdata length"))?
+                .parse()
+                .context("Invalid rdata length")?;
+            let data = parse_hex(&rdata[2..])?;
+            if data.len() != len {
+                return Err(anyhow!("Rdata length does not match its data"));
+            }
+
+            // Decode known types from their wire format.
+            let mut buffer = BytePacketBuffer::with_max_size(MAX_MESSAGE_SIZE);
+            buffer.write_uncompressed_qname(&domain)?;
+            buffer.write_u16(qtype.to_u16())?;
+            buffer.write_u16(class.to_u16())?;
+            buffer.write_u32(ttl)?;
+            buffer.write_u16(data.len() as u16)?;
+            buffer.write_bytes(&data)?;
+            let mut buffer = BytePacketBuffer::from_bytes(buffer.as_bytes());
+            let record = DnsRecord::read(&mut buffer)?;
+            if buffer.position() != buffer.as_bytes().len() {
+                return Err(anyhow!("Trailing bytes in rdata"));
+            }
+            return Ok(record);
+        }
+
+        let field = |i: usize| -> Result<&str> {
+            rdata
+                .get(i)
+                .map(String::as_str)
+                .ok_or_else(|| anyhow!("Missing rdata field"))
+        };
+        let name = |i: usize| -> Result<Name> { Name::from_str_relative(field(i)?, origin) };
+        let expect_fields = |n: usize| -> Result<()> {
+            if rdata.len() != n {
+                return Err(anyhow!(
+                    "Expected {} rdata fields, found {}",
+                    n,
+                    rdata.len()
+                ));
+            }
+            Ok(())
+        };
+
+        match qtype {
+            QueryType::A => {
+                expect_fields(1)?;
+                Ok(DnsRecord::A {
+                    domain,
+                    address: field(0)?.parse()?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::AAAA => {
+                expect_fields(1)?;
+                Ok(DnsRecord::AAAA {
+                    domain,
+                    address: field(0)?.parse()?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::NS => {
+                expect_fields(1)?;
+                Ok(DnsRecord::NS {
+                    domain,
+                    name_server: name(0)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::CNAME => {
+                expect_fields(1)?;
+                Ok(DnsRecord::CNAME {
+                    domain,
+                    alias: name(0)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::PTR => {
+                expect_fields(1)?;
+                Ok(DnsRecord::PTR {
+                    domain,
+                    host: name(0)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::MX => {
+                expect_fields(2)?;
+                Ok(DnsRecord::MX {
+                    domain,
+                    preference: field(0)?.parse()?,
+                    host: name(1)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::SOA => {
+                expect_fields(7)?;
+                Ok(DnsRecord::SOA {
+                    domain,
+                    mname: name(0)?,
+                    rname: name(1)?,
+                    serial: field(2)?.parse()?,
+                    refresh: parse_ttl(field(3)?)?,
+                    retry: parse_ttl(field(4)?)?,
+                    expire: parse_ttl(field(5)?)?,
+                    minimum: parse_ttl(field(6)?)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::TXT | QueryType::SPF => {
+                let data = rdata
+                    .iter()
+                    .map(|field| parse_character_string(field))
+                    .collect::<Result<Vec<_>>>()?;
+                if data.is_empty() {
+                    return Err(anyhow!("Missing character string"));
+                }
+
+                if qtype == QueryType::TXT {
+                    Ok(DnsRecord::TXT {
+                        domain,
+                        data,
+                        class,
+                        ttl,
+                    })
+                } else {
+                    Ok(DnsRecord::SPF {
+                        domain,
+                        data,
+                        class,
+                        ttl,
+                    })
+                }
+            }
+            QueryType::SRV => {
+                expect_fields(4)?;
+                Ok(DnsRecord::SRV {
+                    domain,
+                    priority: field(0)?.parse()?,
+                    weight: field(1)?.parse()?,
+                    port: field(2)?.parse()?,
+                    target: name(3)?,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::DS => Err(anyhow!("DS records need generic rdata")),
+            QueryType::OPT => Err(anyhow!("OPT records have no presentation format")),
+            QueryType::ANY => Err(anyhow!("ANY is not a record type")),
+            QueryType::Unknown(_) => Err(anyhow!("Unknown types need generic rdata")),
+        }
+    }
+
     pub fn read(buffer: &mut BytePacketBuffer) -> Result<DnsRecord> {
-        let mut domain = String::new();
-        buffer.read_qname(&mut domain)?;
+        let domain = buffer.read_qname()?;
         let qtype_num = buffer.read_u16()?;
         let qtype = QueryType::from_u16(qtype_num);
-        let _ = buffer.read_u16()?;
+        let class_num = buffer.read_u16()?;
+        let class = DnsClass::from_u16(class_num);
         let ttl = buffer.read_u32()?;
         let data_len = buffer.read_u16()?;
+        let start = buffer.position();
 
-        match qtype {
+        let record = match qtype {
             QueryType::A => {
                 let raw_addr = buffer.read_u32()?;
                 let address = Ipv4Addr::new(
@@ -60,36 +402,50 @@ impl DnsRecord {
                     ((raw_addr >> 8) & 0xFF) as u8,
                     (raw_addr & 0xFF) as u8,
                 );
-                Ok(DnsRecord::A {
+                DnsRecord::A {
                     domain,
                     address,
+                    class,
                     ttl,
-                })
+                }
             }
             QueryType::NS => {
-                let mut name_server = String::new();
-                buffer.read_qname(&mut name_server)?;
-                Ok(DnsRecord::NS {
+                let name_server = buffer.read_qname()?;
+                DnsRecord::NS {
                     domain,
                     name_server,
+                    class,
                     ttl,
-                })
+                }
             }
             QueryType::CNAME => {
-                let mut alias = String::new();
-                buffer.read_qname(&mut alias)?;
-                Ok(DnsRecord::CNAME { domain, alias, ttl })
+                let alias = buffer.read_qname()?;
+                DnsRecord::CNAME {
+                    domain,
+                    alias,
+                    class,
+                    ttl,
+                }
+            }
+            QueryType::PTR => {
+                let host = buffer.read_qname()?;
+                DnsRecord::PTR {
+                    domain,
+                    host,
+                    class,
+                    ttl,
+                }
             }
             QueryType::MX => {
                 let preference = buffer.read_u16()?;
-                let mut host = String::new();
-                buffer.read_qname(&mut host)?;
-                Ok(DnsRecord::MX {
+                let host = buffer.read_qname()?;
+                DnsRecord::MX {
                     domain,
                     preference,
                     host,
+                    class,
                     ttl,
-                })
+                }
             }
             QueryType::AAAA => {
                 let raw_addr1 = buffer.read_u32()?;
@@ -108,19 +464,87 @@ impl DnsRecord {
                     (raw_addr4 & 0xFFFF) as u16,
                 );
 
-                Ok(DnsRecord::AAAA {
+                DnsRecord::AAAA {
                     domain,
                     address,
+                    class,
                     ttl,
-                })
+                }
             }
-            _ => Ok(DnsRecord::UNKNOWN {
+            QueryType::SOA => {
+                let mname = buffer.read_qname()?;
+                let rname = buffer.read_qname()?;
+                let serial = buffer.read_u32()?;
+                let refresh = buffer.read_u32()?;
+                let retry = buffer.read_u32()?;
+                let expire = buffer.read_u32()?;
+                let minimum = buffer.read_u32()?;
+
+                DnsRecord::SOA {
+                    domain,
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                    class,
+                    ttl,
+                }
+            }
+            QueryType::TXT => DnsRecord::TXT {
+                domain,
+                data: read_character_strings(buffer, data_len)?,
+                class,
+                ttl,
+            },
+            QueryType::SPF => DnsRecord::SPF {
+                domain,
+                data: read_character_strings(buffer, data_len)?,
+                class,
+                ttl,
+            },
+            QueryType::SRV => {
+                let priority = buffer.read_u16()?;
+                let weight = buffer.read_u16()?;
+                let port = buffer.read_u16()?;
+                let target = buffer.read_qname()?;
+                DnsRecord::SRV {
+                    domain,
+                    priority,
+                    weight,
+                    port,
+                    target,
+                    class,
+                    ttl,
+                }
+            }
+            QueryType::OPT => DnsRecord::OPT {
+                edns: Edns::read(buffer, class_num, ttl, data_len)?,
+            },
+            _ => DnsRecord::UNKNOWN {
                 domain,
                 qtype: qtype_num,
-                data_len,
+                class,
+                data: buffer.read_bytes(data_len as usize)?,
                 ttl,
-            }),
+            },
+        };
+
+        // The rdata must fill exactly RDLENGTH bytes, or the records after
+        // it would be read from the wrong place.
+        if buffer.position() - start != data_len as usize {
+            return Err(anyhow!(
+                "{} record for {} has {} bytes of rdata, but RDLENGTH {}",
+                qtype,
+                record.domain(),
+                buffer.position() - start,
+                data_len
+            ));
         }
+
+        Ok(record)
     }
 
     pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize> {
@@ -130,11 +554,12 @@ impl DnsRecord {
             DnsRecord::A {
                 ref domain,
                 ref address,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::A.to_u16())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_u16())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(4)?;
                 let octets = address.octets();
@@ -146,11 +571,12 @@ impl DnsRecord {
             DnsRecord::NS {
                 ref domain,
                 ref name_server,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::NS.to_u16())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_u16())?;
                 buffer.write_u32(ttl)?;
                 let pos = buffer.position();
                 buffer.write_u16(0)?;
@@ -161,11 +587,12 @@ impl DnsRecord {
             DnsRecord::CNAME {
                 ref domain,
                 ref alias,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::CNAME.to_u16())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_u16())?;
                 buffer.write_u32(ttl)?;
                 let pos = buffer.position();
                 buffer.write_u16(0)?;
@@ -173,15 +600,32 @@ impl DnsRecord {
                 let len = buffer.position() - pos - 2;
                 buffer.set_u16(pos, len as u16)?;
             }
+            DnsRecord::PTR {
+                ref domain,
+                ref host,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::PTR.to_u16())?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                let pos = buffer.position();
+                buffer.write_u16(0)?;
+                buffer.write_qname(host)?;
+                let len = buffer.position() - pos - 2;
+                buffer.set_u16(pos, len as u16)?;
+            }
             DnsRecord::MX {
                 ref domain,
                 preference,
                 ref host,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::MX.to_u16())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_u16())?;
                 buffer.write_u32(ttl)?;
                 let pos = buffer.position();
                 buffer.write_u16(0)?;
@@ -193,22 +637,282 @@ impl DnsRecord {
             DnsRecord::AAAA {
                 ref domain,
                 ref address,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::AAAA.to_u16())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_u16())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(16)?;
                 for segment in address.segments() {
                     buffer.write_u16(segment)?;
                 }
             }
-            DnsRecord::UNKNOWN { .. } => {
-                println!("Skipping record: {:?}", self);
+            DnsRecord::SOA {
+                ref domain,
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SOA.to_u16())?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                let pos = buffer.position();
+                buffer.write_u16(0)?;
+                buffer.write_qname(mname)?;
+                buffer.write_qname(rname)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
+                let len = buffer.position() - pos - 2;
+                buffer.set_u16(pos, len as u16)?;
+            }
+            DnsRecord::TXT {
+                ref domain,
+                ref data,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::TXT.to_u16())?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                let pos = buffer.position();
+                buffer.write_u16(0)?;
+                write_character_strings(buffer, data)?;
+                let len = buffer.position() - pos - 2;
+                buffer.set_u16(pos, len as u16)?;
+            }
+            DnsRecord::SPF {
+                ref domain,
+                ref data,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SPF.to_u16())?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                let pos = buffer.position();
+                buffer.write_u16(0)?;
+                write_character_strings(buffer, data)?;
+                let len = buffer.position() - pos - 2;
+                buffer.set_u16(pos, len as u16)?;
+            }
+            DnsRecord::SRV {
+                ref domain,
+                priority,
+                weight,
+                port,
+                ref target,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SRV.to_u16())?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                let pos = buffer.position();
+                buffer.write_u16(0)?;
+                buffer.write_u16(priority)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+                // RFC 2782 forbids compressing the target.
+                buffer.write_uncompressed_qname(target)?;
+                let len = buffer.position() - pos - 2;
+                buffer.set_u16(pos, len as u16)?;
+            }
+            DnsRecord::OPT { ref edns } => {
+                edns.write(buffer)?;
+            }
+            DnsRecord::UNKNOWN {
+                ref domain,
+                qtype,
+                class,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(qtype)?;
+                buffer.write_u16(class.to_u16())?;
+                buffer.write_u32(ttl)?;
+                buffer.write_u16(data.len() as u16)?;
+                buffer.write_bytes(data)?;
             }
         }
 
         Ok(buffer.position() - start_pos)
     }
 }
+
+impl fmt::Display for DnsRecord {
+    /// Write the record in presentation format: `name TTL class TYPE rdata`.
+    ///
+    /// The OPT pseudo-record is written the way dig shows it instead.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let DnsRecord::OPT { edns } = self {
+            return write!(f, "{}", edns);
+        }
+
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t",
+            self.domain(),
+            self.ttl(),
+            self.class().unwrap_or_default(),
+            self.query_type()
+        )?;
+
+        self.rdata().fmt(f)
+    }
+}
+
+/// The rdata of a record in presentation format, see `DnsRecord::rdata`.
+pub struct RdataDisplay<'a>(&'a DnsRecord);
+
+impl fmt::Display for RdataDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            DnsRecord::A { address, .. } => write!(f, "{}", address),
+            DnsRecord::AAAA { address, .. } => write!(f, "{}", address),
+            DnsRecord::NS { name_server, .. } => write!(f, "{}", name_server),
+            DnsRecord::CNAME { alias, .. } => write!(f, "{}", alias),
+            DnsRecord::PTR { host, .. } => write!(f, "{}", host),
+            DnsRecord::MX {
+                preference, host, ..
+            } => write!(f, "{} {}", preference, host),
+            DnsRecord::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            DnsRecord::TXT { data, .. } | DnsRecord::SPF { data, .. } => {
+                for (i, string) in data.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write_character_string(f, string)?;
+                }
+                Ok(())
+            }
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                target,
+                ..
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            DnsRecord::UNKNOWN { data, .. } => f.write_str(&generic_rdata(data)),
+            DnsRecord::OPT { edns } => write!(f, "{}", edns),
+        }
+    }
+}
+
+impl FromStr for DnsRecord {
+    type Err = anyhow::Error;
+
+    /// Parse a record in presentation format, `name TTL [class] TYPE rdata`.
+    ///
+    /// The TTL and class may come in either order; the class defaults to IN.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = tokenize(s)?;
+        let mut fields = fields.iter();
+
+        let domain: Name = fields
+            .next()
+            .ok_or_else(|| anyhow!("Missing owner name"))?
+            .parse()?;
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let field = fields
+                .next()
+                .ok_or_else(|| anyhow!("Missing record type"))?;
+            let upper = field.to_ascii_uppercase();
+            if ttl.is_none() && field.bytes().all(|b| b.is_ascii_digit()) {
+                ttl = Some(field.parse().context("Invalid TTL")?);
+            } else if class.is_none() && upper.parse::<DnsClass>().is_ok() {
+                class = upper.parse().ok();
+            } else {
+                break upper.parse::<QueryType>()?;
+            }
+        };
+
+        let ttl = ttl.ok_or_else(|| anyhow!("Missing TTL"))?;
+        let rdata: Vec<String> = fields.cloned().collect();
+        DnsRecord::from_rdata(
+            domain,
+            qtype,
+            class.unwrap_or_default(),
+            ttl,
+            &rdata,
+            &Name::root(),
+        )
+        .with_context(|| format!("Invalid {} record", qtype))
+    }
+}
+
+/// Read the `<character-string>`s filling `data_len` bytes of rdata.
+fn read_character_strings(buffer: &mut BytePacketBuffer, data_len: u16) -> Result<Vec<Vec<u8>>> {
+    let mut strings = Vec::new();
+    let end = buffer.position() + data_len as usize;
+
+    while buffer.position() < end {
+        let len = buffer.read_u8()?;
+        strings.push(buffer.read_bytes(len as usize)?);
+    }
+
+    if buffer.position() != end {
+        return Err(anyhow!("Character string overflows its record"));
+    }
+
+    Ok(strings)
+}
+
+/// Write each string as a length-prefixed `<character-string>`.
+fn write_character_strings(buffer: &mut BytePacketBuffer, strings: &[Vec<u8>]) -> Result<()> {
+    if strings.is_empty() {
+        // The rdata must hold at least one, possibly empty, string.
+        return buffer.write_u8(0);
+    }
+
+    for string in strings {
+        if string.len() > 0xFF {
+            return Err(anyhow!("Character string exceeds 255 bytes of length"));
+        }
+
+        buffer.write_u8(string.len() as u8)?;
+        buffer.write_bytes(string)?;
+    }
+
+    Ok(())
+}
+
+/// Render rdata in the generic `\# <length> <hex>` presentation format (RFC 3597 section 5).
+pub fn generic_rdata(data: &[u8]) -> String {
+    let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+    if hex.is_empty() {
+        return "\\# 0".to_string();
+    }
+
+    format!("\\# {} {}", data.len(), hex)
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use crate::{BytePacketBuffer, Name, QueryType};
+
+/// UDP payload size advertised in our queries and responses.
+///
+/// 1232 bytes avoids IP fragmentation on virtually every path (DNS Flag Day 2020).
+pub const EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+/// The only EDNS version defined so far (RFC 6891).
+pub const EDNS_VERSION: u8 = 0;
+
+const DNSSEC_OK: u16 = 1 << 15;
+
+/// A single option carried in the rdata of an OPT record.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+/// The EDNS(0) OPT pseudo-record (RFC 6891).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Edns {
+    /// The largest UDP payload the sender is able to receive.
+    pub payload_size: u16,
+    /// The upper eight bits of the 12-bit result code.
+    ///
+    /// `DnsPacket` keeps this in sync with `DnsHeader::result_code`.
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Edns::new()
+    }
+}
+
+impl Edns {
+    pub fn new() -> Edns {
+        Edns {
+            payload_size: EDNS_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: EDNS_VERSION,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// Read the rdata of an OPT record whose fixed fields have already been read.
+    ///
+    /// The class holds the payload size and the TTL holds the extended result
+    /// code, the version and the flags.
+    pub fn read(
+        buffer: &mut BytePacketBuffer,
+        class: u16,
+        ttl: u32,
+        data_len: u16,
+    ) -> Result<Edns> {
+        let mut options = Vec::new();
+        let end = buffer.position() + data_len as usize;
+
+        while buffer.position() < end {
+            let code = buffer.read_u16()?;
+            let len = buffer.read_u16()?;
+            let data = buffer.read_bytes(len as usize)?;
+            options.push(EdnsOption { code, data });
+        }
+
+        if buffer.position() != end {
+            return Err(anyhow!("OPT option overflows its record"));
+        }
+
+        Ok(Edns {
+            payload_size: class,
+            extended_rcode: (ttl >> 24) as u8,
+            version: ((ttl >> 16) & 0xFF) as u8,
+            dnssec_ok: (ttl as u16 & DNSSEC_OK) > 0,
+            options,
+        })
+    }
+
+    /// Write the complete OPT record, owner name included.
+    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<()> {
+        buffer.write_qname(&Name::root())?;
+        buffer.write_u16(QueryType::OPT.to_u16())?;
+        buffer.write_u16(self.payload_size)?;
+        buffer.write_u8(self.extended_rcode)?;
+        buffer.write_u8(self.version)?;
+        buffer.write_u16(if self.dnssec_ok { DNSSEC_OK } else { 0 })?;
+
+        let pos = buffer.position();
+        buffer.write_u16(0)?;
+        for option in &self.options {
+            buffer.write_u16(option.code)?;
+            buffer.write_u16(option.data.len() as u16)?;
+            buffer.write_bytes(&option.data)?;
+        }
+        let len = buffer.position() - pos - 2;
+        buffer.set_u16(pos, len as u16)
+    }
+}
+
+impl fmt::Display for Edns {
+    /// Write the OPT pseudo-section the way dig shows it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = if self.dnssec_ok { " do" } else { "" };
+        write!(
+            f,
+            "; EDNS: version: {}, flags:{}; udp: {}",
+            self.version, flags, self.payload_size
+        )?;
+
+        for option in &self.options {
+            let hex: String = option.data.iter().map(|b| format!("{:02x}", b)).collect();
+            write!(f, "\n; OPT={}: {}", option.code, hex)?;
+        }
+
+        Ok(())
+    }
+}
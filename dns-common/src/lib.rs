@@ -1,17 +1,35 @@
-pub use byte_packet_buffer::BytePacketBuffer;
+pub use byte_packet_buffer::{BytePacketBuffer, MAX_MESSAGE_SIZE, UDP_MESSAGE_SIZE};
+pub use dns_class::DnsClass;
 pub use dns_header::DnsHeader;
-pub use dns_packet::DnsPacket;
+pub use dns_packet::{DisplayOptions, DnsPacket, PacketDisplay};
 pub use dns_question::DnsQuestion;
-pub use dns_record::DnsRecord;
-pub use operations::{lookup, recursive_lookup, socket};
+pub use dns_record::{generic_rdata, DnsRecord, RdataDisplay};
+pub use edns::{Edns, EdnsOption, EDNS_PAYLOAD_SIZE, EDNS_VERSION};
+pub use name::{reverse_name, Name, MAX_LABEL_LENGTH, MAX_NAME_LENGTH};
+pub use operations::{
+    dual_stack_socket, exchange, exchange_any, lookup, lookup_any, recursive_lookup, socket,
+    tcp_lookup, traced_recursive_lookup, Exchange, LookupOptions, TraceStep, MAX_CNAME_CHAIN,
+};
 pub use query_type::QueryType;
 pub use result_code::ResultCode;
+pub use root_hints::{prime_root_servers, root_servers, RootHint, ROOT_HINTS};
+pub use tcp::{read_tcp_message, write_tcp_message};
+pub use zone_file::{parse_zone, read_zone_file};
 
 mod byte_packet_buffer;
+mod dns_class;
 mod dns_header;
 mod dns_packet;
 mod dns_question;
 mod dns_record;
+mod edns;
+mod name;
 mod operations;
+mod presentation;
 mod query_type;
 mod result_code;
+mod root_hints;
+#[cfg(feature = "serde")]
+mod serialization;
+mod tcp;
+mod zone_file;
@@ -0,0 +1,451 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::presentation::read_escape;
+
+/// The longest a name may be in wire format (RFC 1035 section 2.3.4).
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// The longest a single label may be.
+pub const MAX_LABEL_LENGTH: usize = 63;
+
+/// A domain name, kept as the raw bytes of its labels.
+///
+/// Labels keep their case and may hold any byte, including dots. Names compare
+/// and hash case-insensitively (RFC 4343) and sort in canonical order (RFC 4034
+/// section 6.1). Every name is absolute; the root has no labels.
+#[derive(Clone, Default)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+    pub const fn root() -> Name {
+        Name { labels: Vec::new() }
+    }
+
+    /// Build a name from its labels, leftmost first.
+    pub fn from_labels<I, L>(labels: I) -> Result<Name>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<Vec<u8>>,
+    {
+        let name = Name {
+            labels: labels.into_iter().map(Into::into).collect(),
+        };
+
+        for label in &name.labels {
+            if label.is_empty() {
+                return Err(anyhow!("Empty label in domain name"));
+            }
+            if label.len() > MAX_LABEL_LENGTH {
+                return Err(anyhow!("Single label exceeds 63 characters of length"));
+            }
+        }
+        if name.wire_len() > MAX_NAME_LENGTH {
+            return Err(anyhow!("Domain name exceeds 255 bytes of length"));
+        }
+
+        Ok(name)
+    }
+
+    /// The labels of the name, leftmost first.
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        self.labels.iter().map(Vec::as_slice)
+    }
+
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The length of the uncompressed name in wire format.
+    pub fn wire_len(&self) -> usize {
+        self.labels
+            .iter()
+            .map(|label| label.len() + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    /// The name with its leftmost label removed, or `None` for the root.
+    pub fn parent(&self) -> Option<Name> {
+        if self.is_root() {
+            return None;
+        }
+
+        Some(Name {
+            labels: self.labels[1..].to_vec(),
+        })
+    }
+
+    /// The name itself, followed by each of its parents up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = Name> + '_ {
+        (0..=self.labels.len()).map(move |i| Name {
+            labels: self.labels[i..].to_vec(),
+        })
+    }
+
+    /// Whether the name is `zone` or lies below it.
+    ///
+    /// Whole labels are compared, so `evilexample.com` is not below
+    /// `example.com`. Every name is below the root.
+    pub fn is_subdomain_of(&self, zone: &Name) -> bool {
+        self.labels.len() >= zone.labels.len()
+            && self
+                .labels
+                .iter()
+                .rev()
+                .zip(zone.labels.iter().rev())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// Of `zones`, the deepest one the name lies in, i.e. the closest zone cut above it.
+    pub fn closest_zone<'a>(&self, zones: impl IntoIterator<Item = &'a Name>) -> Option<&'a Name> {
+        zones
+            .into_iter()
+            .filter(|zone| self.is_subdomain_of(zone))
+            .max_by_key(|zone| zone.label_count())
+    }
+
+    /// The name with `label` prepended.
+    pub fn child(&self, label: impl Into<Vec<u8>>) -> Result<Name> {
+        let mut labels = vec![label.into()];
+        labels.extend(self.labels.iter().cloned());
+        Name::from_labels(labels)
+    }
+
+    /// The labels of this name followed by those of `origin`, for completing
+    /// relative names.
+    pub fn append(&self, origin: &Name) -> Result<Name> {
+        Name::from_labels(self.labels.iter().chain(&origin.labels).cloned())
+    }
+
+    /// Parse a name in presentation format that is relative to `origin`
+    /// unless it ends in a dot, as in zone files. `@` stands for the origin.
+    pub fn from_str_relative(s: &str, origin: &Name) -> Result<Name> {
+        if s == "@" {
+            return Ok(origin.clone());
+        }
+
+        // Only an unescaped trailing dot makes the name absolute.
+        let mut escaped = false;
+        let mut absolute = false;
+        for b in s.bytes() {
+            absolute = b == b'.' && !escaped;
+            escaped = b == b'\\' && !escaped;
+        }
+
+        let name: Name = s.parse()?;
+        if absolute {
+            Ok(name)
+        } else {
+            name.append(origin)
+                .with_context(|| format!("Invalid domain name {}", s))
+        }
+    }
+
+    pub fn to_lowercase(&self) -> Name {
+        Name {
+            labels: self
+                .labels
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(&other.labels)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.labels.len());
+        for label in &self.labels {
+            state.write_usize(label.len());
+            for b in label {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    /// Canonical order: compare labels right to left as lowercase bytes.
+    fn cmp(&self, other: &Name) -> Ordering {
+        let lower = |label: &Vec<u8>| label.to_ascii_lowercase();
+        self.labels
+            .iter()
+            .rev()
+            .map(lower)
+            .cmp(other.labels.iter().rev().map(lower))
+    }
+}
+
+impl FromStr for Name {
+    type Err = anyhow::Error;
+
+    /// Parse a name in presentation format, with or without the trailing dot.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "." {
+            return Ok(Name::root());
+        }
+
+        let mut labels = Vec::new();
+        let mut label = Vec::new();
+        let mut bytes = s.bytes();
+
+        while let Some(b) = bytes.next() {
+            match b {
+                b'.' => {
+                    if label.is_empty() {
+                        return Err(anyhow!("Empty label in domain name {}", s));
+                    }
+                    labels.push(std::mem::take(&mut label));
+                }
+                b'\\' => label.push(
+                    read_escape(&mut bytes)
+                        .with_context(|| format!("Invalid domain name {}", s))?,
+                ),
+                _ => label.push(b),
+            }
+        }
+
+        if !label.is_empty() {
+            labels.push(label);
+        }
+
+        Name::from_labels(labels)
+    }
+}
+
+impl fmt::Display for Name {
+    /// Write the name in presentation format with a trailing dot, escaping
+    /// special and non-printable bytes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+
+        for label in &self.labels {
+            for &b in label {
+                match b {
+                    b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                        write!(f, "\\{}", b as char)?
+                    }
+                    0x21..=0x7E => write!(f, "{}", b as char)?,
+                    _ => write!(f, "\\{:03}", b)?,
+                }
+            }
+            f.write_str(".")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+/// The name to query for PTR records of `ip`, e.g. `4.3.2.1.in-addr.arpa`
+/// for `1.2.3.4`, or a name of reversed nibbles under `ip6.arpa` for IPv6.
+pub fn reverse_name(ip: IpAddr) -> Name {
+    let labels: Vec<String> = match ip {
+        IpAddr::V4(ip) => ip
+            .octets()
+            .iter()
+            .rev()
+            .map(u8::to_string)
+            .chain(["in-addr".to_string(), "arpa".to_string()])
+            .collect(),
+        IpAddr::V6(ip) => ip
+            .octets()
+            .iter()
+            .rev()
+            .flat_map(|b| [b & 0x0F, b >> 4])
+            .map(|n| format!("{:x}", n))
+            .chain(["ip6".to_string(), "arpa".to_string()])
+            .collect(),
+    };
+
+    Name::from_labels(labels).expect("reverse names are short enough")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn hash(name: &Name) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn parse_with_and_without_trailing_dot() {
+        let www = name("www.example.com");
+        assert_eq!(www, name("www.example.com."));
+        assert_eq!(
+            www.labels().collect::<Vec<_>>(),
+            vec![&b"www"[..], b"example", b"com"]
+        );
+        assert_eq!(www.wire_len(), 17);
+        assert!(name(".").is_root());
+        assert!(name("").is_root());
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let long_label = "a".repeat(64);
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        for s in [
+            "www..example.com",
+            ".example.com",
+            long_label.as_str(),
+            long_name.as_str(),
+            "bad\\",
+            "bad\\25",
+            "bad\\256",
+        ] {
+            assert!(s.parse::<Name>().is_err(), "{:?}", s);
+        }
+
+        // 63-byte labels that just fit in 255 bytes on the wire.
+        let longest = format!("{}.{}", vec!["a".repeat(63); 3].join("."), "a".repeat(61));
+        assert_eq!(name(&longest).wire_len(), MAX_NAME_LENGTH);
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let escaped = name("a\\.b.\\065\\ \\\\.example");
+        assert_eq!(
+            escaped.labels().collect::<Vec<_>>(),
+            vec![&b"a.b"[..], b"A \\", b"example"]
+        );
+        assert_eq!(escaped.to_string(), "a\\.b.A\\032\\\\.example.");
+        assert_eq!(name(&escaped.to_string()), escaped);
+
+        let binary = Name::from_labels([vec![0u8, 0x7F, b'@', b'$']]).unwrap();
+        assert_eq!(binary.to_string(), "\\000\\127\\@\\$.");
+        assert_eq!(name(&binary.to_string()), binary);
+    }
+
+    #[test]
+    fn equality_and_hash_ignore_case() {
+        let lower = name("www.example.com");
+        let mixed = name("WWW.Example.COM");
+        assert_eq!(lower, mixed);
+        assert_eq!(hash(&lower), hash(&mixed));
+        assert_eq!(mixed.to_string(), "WWW.Example.COM.");
+        assert_eq!(mixed.to_lowercase().to_string(), "www.example.com.");
+        assert_ne!(lower, name("www.example.org"));
+    }
+
+    #[test]
+    fn from_str_relative_completes_relative_names() {
+        let origin = name("example.com");
+        let cases = [
+            ("@", "example.com."),
+            ("www", "www.example.com."),
+            ("a.b", "a.b.example.com."),
+            ("www.example.org.", "www.example.org."),
+            ("a\\.", "a\\..example.com."),
+            ("a\\\\.", "a\\\\."),
+        ];
+
+        for (s, expected) in cases {
+            let parsed = Name::from_str_relative(s, &origin).unwrap();
+            assert_eq!(parsed.to_string(), expected, "{:?}", s);
+        }
+
+        let too_long = vec!["a".repeat(63); 4].join(".");
+        assert!(Name::from_str_relative(&too_long[..250], &origin).is_err());
+    }
+
+    #[test]
+    fn subdomains_compare_whole_labels() {
+        let zone = name("example.com");
+        assert!(name("www.Example.com").is_subdomain_of(&zone));
+        assert!(zone.is_subdomain_of(&zone));
+        assert!(!name("evilexample.com").is_subdomain_of(&zone));
+        assert!(!name("com").is_subdomain_of(&zone));
+        assert!(zone.is_subdomain_of(&Name::root()));
+
+        let zones = [name("com"), name("example.com"), name("org")];
+        assert_eq!(
+            name("a.b.example.com").closest_zone(&zones),
+            Some(&zones[1])
+        );
+        assert_eq!(name("example.net").closest_zone(&zones), None);
+    }
+
+    #[test]
+    fn canonical_order() {
+        // The example of RFC 4034 section 6.1.
+        let sorted = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\\001.z.example",
+            "*.z.example",
+            "\\200.z.example",
+        ]
+        .map(name);
+
+        let mut shuffled = sorted.clone();
+        shuffled.reverse();
+        shuffled.swap(2, 6);
+        shuffled.sort();
+        assert_eq!(shuffled, sorted);
+        assert_eq!(name("A.example").cmp(&name("a.EXAMPLE")), Ordering::Equal);
+    }
+
+    #[test]
+    fn reverse_names() {
+        assert_eq!(
+            reverse_name("192.0.2.1".parse().unwrap()).to_string(),
+            "1.2.0.192.in-addr.arpa."
+        );
+        assert_eq!(
+            reverse_name("2001:db8::1".parse().unwrap()).to_string(),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+        );
+    }
+}
@@ -1,10 +1,16 @@
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_recursion::async_recursion;
-use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs, UdpSocket};
+use tokio::time::timeout;
+use tracing::warn;
 
-use crate::{BytePacketBuffer, DnsPacket, DnsQuestion, QueryType, ResultCode};
+use crate::{
+    read_tcp_message, write_tcp_message, BytePacketBuffer, DnsPacket, DnsQuestion, DnsRecord, Edns,
+    Name, QueryType, ResultCode, EDNS_PAYLOAD_SIZE, MAX_MESSAGE_SIZE,
+};
 
 pub async fn socket(addr: impl ToSocketAddrs) -> Result<UdpSocket> {
     UdpSocket::bind(addr)
@@ -12,35 +18,268 @@ pub async fn socket(addr: impl ToSocketAddrs) -> Result<UdpSocket> {
         .context("Failed to bind to local socket")
 }
 
-#[async_recursion]
+/// Bind a socket on `port` that can reach both IPv4 and IPv6 servers.
+///
+/// Falls back to an IPv4-only socket on hosts without IPv6.
+pub async fn dual_stack_socket(port: u16) -> Result<UdpSocket> {
+    match UdpSocket::bind((Ipv6Addr::UNSPECIFIED, port)).await {
+        Ok(socket) => Ok(socket),
+        Err(_) => socket(("0.0.0.0", port)).await,
+    }
+}
+
+/// Timeout and retry behaviour of a lookup.
+#[derive(Debug, Clone)]
+pub struct LookupOptions {
+    /// How long to wait for the first attempt; doubled on every retry.
+    pub timeout: Duration,
+    /// How many times to retry a server before failing over to the next.
+    pub retries: u32,
+    /// Whether to ask the server to recurse on our behalf (the RD flag).
+    pub recursion_desired: bool,
+    /// Whether to ask the server not to validate DNSSEC (the CD flag).
+    pub checking_disabled: bool,
+    /// Whether to ask for DNSSEC records (the DO bit).
+    pub dnssec_ok: bool,
+    /// The UDP payload size to advertise in the OPT record.
+    pub payload_size: u16,
+}
+
+impl Default for LookupOptions {
+    fn default() -> Self {
+        LookupOptions {
+            timeout: Duration::from_secs(2),
+            retries: 2,
+            recursion_desired: true,
+            checking_disabled: false,
+            dnssec_ok: false,
+            payload_size: EDNS_PAYLOAD_SIZE,
+        }
+    }
+}
+
+/// A response together with where it came from and what it took to get it.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub response: DnsPacket,
+    /// The server that answered.
+    pub server: SocketAddr,
+    /// The size of the response message in bytes.
+    pub size: usize,
+    /// The time from sending the first query to receiving the response,
+    /// including retries.
+    pub elapsed: Duration,
+    /// Whether the response came over TCP.
+    pub tcp: bool,
+}
+
+/// One query sent while resolving a name iteratively.
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    pub qname: Name,
+    pub qtype: QueryType,
+    /// The zone the queried servers were expected to be authoritative for.
+    pub zone: Name,
+    /// The response as received, before out-of-zone records were dropped.
+    pub exchange: Exchange,
+    /// The zone the response delegated to, if resolution followed it.
+    pub referral: Option<Name>,
+    /// The glue addresses of the servers for `referral`, which are queried
+    /// next. Empty if the name servers had to be resolved first.
+    pub glue: Vec<IpAddr>,
+}
+
+/// Give up on names whose resolution takes more referrals than this.
+const MAX_REFERRALS: usize = 16;
+
+/// Give up on name servers that can only be resolved through this many nested
+/// resolutions of other name servers without glue, which are likely a cycle.
+const MAX_NS_DEPTH: usize = 4;
+
+/// Give up on CNAME chains longer than this, which are likely a loop.
+pub const MAX_CNAME_CHAIN: usize = 8;
+
+/// Resolve `qname` iteratively, starting at `dns_servers` and following
+/// referrals down the delegation tree.
+///
+/// Pass `root_servers()` to resolve from the root, or a recursive resolver to
+/// let it do the work. Queries are sent with the RD flag cleared.
+///
+/// CNAMEs are chased across zones. The answer section of the result holds the
+/// whole chain followed by the records of the name it ends at.
 pub async fn recursive_lookup(
     socket: &UdpSocket,
-    dns_server: Ipv4Addr,
-    qname: &str,
+    dns_servers: &[IpAddr],
+    qname: &Name,
+    qtype: QueryType,
+    options: &LookupOptions,
+) -> Result<DnsPacket> {
+    let mut trace = Vec::new();
+    traced_recursive_lookup(socket, dns_servers, qname, qtype, options, &mut trace).await
+}
+
+/// Like `recursive_lookup`, but record every query sent on the way in `trace`,
+/// including those resolving name servers that came without glue.
+///
+/// The steps are recorded as they happen, so `trace` shows how far resolution
+/// got even if it fails.
+pub async fn traced_recursive_lookup(
+    socket: &UdpSocket,
+    dns_servers: &[IpAddr],
+    qname: &Name,
+    qtype: QueryType,
+    options: &LookupOptions,
+    trace: &mut Vec<TraceStep>,
+) -> Result<DnsPacket> {
+    nested_lookup(socket, dns_servers, qname, qtype, options, trace, 0).await
+}
+
+/// Like `traced_recursive_lookup`, for a lookup nested `depth` levels deep in
+/// resolving name servers that came without glue.
+#[async_recursion]
+async fn nested_lookup(
+    socket: &UdpSocket,
+    dns_servers: &[IpAddr],
+    qname: &Name,
+    qtype: QueryType,
+    options: &LookupOptions,
+    trace: &mut Vec<TraceStep>,
+    depth: usize,
+) -> Result<DnsPacket> {
+    let options = &LookupOptions {
+        recursion_desired: false,
+        ..options.clone()
+    };
+
+    let mut response = iterate(socket, dns_servers, qname, qtype, options, trace, depth).await?;
+    if qtype == QueryType::CNAME || qtype == QueryType::ANY {
+        return Ok(response);
+    }
+
+    let mut answers: Vec<DnsRecord> = Vec::new();
+    let mut chain = vec![qname.clone()];
+
+    loop {
+        // Follow as much of the chain as this response covers.
+        let chain_len = chain.len();
+        let mut name = chain[chain_len - 1].clone();
+        while let Some(alias) = response.get_cname(&name) {
+            if chain.contains(alias) {
+                return Err(anyhow!("CNAME loop at {}", alias));
+            }
+            if chain.len() > MAX_CNAME_CHAIN {
+                return Err(anyhow!("CNAME chain for {} is too long", qname));
+            }
+            name = alias.clone();
+            chain.push(name.clone());
+        }
+
+        let resolved = response
+            .answers
+            .iter()
+            .any(|r| r.query_type() == qtype && r.domain() == &name);
+
+        for rec in response.answers.drain(..) {
+            if !answers.contains(&rec) {
+                answers.push(rec);
+            }
+        }
+
+        if resolved
+            || chain.len() == chain_len
+            || response.header.result_code != ResultCode::NOERROR
+        {
+            break;
+        }
+
+        // The chain leaves the zone, so resolve its target from the top.
+        response = iterate(socket, dns_servers, &name, qtype, options, trace, depth).await?;
+    }
+
+    response.questions = vec![DnsQuestion::new(qname.clone(), qtype)];
+    response.answers = answers;
+    Ok(response)
+}
+
+/// Follow referrals for `qname` until a server answers authoritatively.
+async fn iterate(
+    socket: &UdpSocket,
+    dns_servers: &[IpAddr],
+    qname: &Name,
     qtype: QueryType,
+    options: &LookupOptions,
+    trace: &mut Vec<TraceStep>,
+    depth: usize,
 ) -> Result<DnsPacket> {
-    let mut ns = dns_server;
+    let mut ns = dns_servers.to_vec();
+    // The zone the active servers are authoritative for.
+    let mut zone = Name::root();
+    let mut referrals = 0;
 
     loop {
-        println!("attempting lookup of {:?} {} with ns {}", qtype, qname, ns);
+        // The next step is to send the query to the active servers.
+        let servers: Vec<SocketAddr> = ns.iter().map(|&ip| (ip, 53).into()).collect();
+        let exchange = exchange_any(socket, &servers, qname, &qtype, options).await?;
+        let server = exchange.server;
+        let mut response = exchange.response.clone();
+        response.strip_out_of_zone(&zone);
 
-        // The next step is to send the query to the active server.
-        let ns_copy = ns;
+        // If there are entries in the answer section, and no errors, we are
+        // done, and likewise if the name does not exist.
+        let result_code = response.header.result_code;
+        let answered = (!response.answers.is_empty() && result_code == ResultCode::NOERROR)
+            || result_code == ResultCode::NXDOMAIN;
 
-        let server = (ns_copy, 53);
-        let response = lookup(socket, server, &qname.to_string(), &qtype).await?;
+        // Only follow referrals that lead further down towards qname, so
+        // servers cannot send us sideways or back up the tree.
+        let referral = match response.get_referral_zone(qname) {
+            Some(referral) if !answered && referral != &zone => Some(referral.clone()),
+            _ => None,
+        };
+        let glue = match referral {
+            Some(_) => response.get_resolved_ns(qname),
+            None => Vec::new(),
+        };
+
+        trace.push(TraceStep {
+            qname: qname.clone(),
+            qtype,
+            zone: zone.clone(),
+            exchange,
+            referral: referral.clone(),
+            glue: glue.clone(),
+        });
+
+        // A server that neither answers nor refers us on is lame, and its
+        // empty answer must not be taken for NODATA.
+        let lame = referral.is_none()
+            && result_code == ResultCode::NOERROR
+            && response.answers.is_empty()
+            && !response
+                .authorities
+                .iter()
+                .any(|r| r.query_type() == QueryType::SOA);
 
-        // If there are entries in the answer section, and no errors, we are done!
-        if !response.answers.is_empty() && response.header.result_code == ResultCode::NOERROR {
-            return Ok(response);
+        match referral {
+            Some(referral) => zone = referral,
+            None if lame => {
+                warn!("ns {} is lame for {}", server, zone);
+                ns.retain(|&ip| ip != server.ip());
+                if ns.is_empty() {
+                    return Err(anyhow!("Every name server for {} is lame", zone));
+                }
+                continue;
+            }
+            None => return Ok(response),
         }
 
-        if response.header.result_code == ResultCode::NXDOMAIN {
-            return Ok(response);
+        referrals += 1;
+        if referrals > MAX_REFERRALS {
+            return Err(anyhow!("Too many referrals resolving {}", qname));
         }
 
-        if let Some(new_ns) = response.get_resolved_ns(qname) {
-            ns = new_ns;
+        if !glue.is_empty() {
+            ns = glue;
             continue;
         }
 
@@ -51,57 +290,330 @@ pub async fn recursive_lookup(
             }
         };
 
-        let recursive_response = recursive_lookup(socket, ns, new_ns_name, QueryType::A).await?;
+        // The name server has no glue, so resolve it from the top again.
+        match resolve_ns(socket, dns_servers, new_ns_name, options, trace, depth + 1).await? {
+            Some(address) => ns = vec![address],
+            None => return Ok(response),
+        }
+    }
+}
+
+/// Look up an address for a name server, falling back to IPv6 for name
+/// servers that only have AAAA records.
+async fn resolve_ns(
+    socket: &UdpSocket,
+    dns_servers: &[IpAddr],
+    ns_name: &Name,
+    options: &LookupOptions,
+    trace: &mut Vec<TraceStep>,
+    depth: usize,
+) -> Result<Option<IpAddr>> {
+    if depth > MAX_NS_DEPTH {
+        return Err(anyhow!(
+            "Name servers without glue nested too deep resolving {}",
+            ns_name
+        ));
+    }
+
+    let response = nested_lookup(
+        socket,
+        dns_servers,
+        ns_name,
+        QueryType::A,
+        options,
+        trace,
+        depth,
+    )
+    .await;
+    if let Some(address) = response.ok().and_then(|r| r.get_random_a()) {
+        return Ok(Some(IpAddr::V4(address)));
+    }
+
+    let response = nested_lookup(
+        socket,
+        dns_servers,
+        ns_name,
+        QueryType::AAAA,
+        options,
+        trace,
+        depth,
+    )
+    .await?;
+    Ok(response.get_random_aaaa().map(IpAddr::V6))
+}
+
+/// Query each of `dns_servers` in turn until one of them answers.
+pub async fn lookup_any(
+    socket: &UdpSocket,
+    dns_servers: &[SocketAddr],
+    name: &Name,
+    qtype: &QueryType,
+    options: &LookupOptions,
+) -> Result<DnsPacket> {
+    let exchange = exchange_any(socket, dns_servers, name, qtype, options).await?;
+    Ok(exchange.response)
+}
 
-        if let Some(new_ns) = recursive_response.get_random_a() {
-            ns = new_ns;
-        } else {
-            return Ok(response);
+/// Like `lookup_any`, but also report which server answered and how.
+///
+/// A server that answers SERVFAIL, REFUSED or FORMERR is skipped like one
+/// that does not answer; its answer is only returned if every server fails.
+pub async fn exchange_any(
+    socket: &UdpSocket,
+    dns_servers: &[SocketAddr],
+    name: &Name,
+    qtype: &QueryType,
+    options: &LookupOptions,
+) -> Result<Exchange> {
+    let mut last_error = anyhow!("No DNS servers to query");
+    let mut last_failure = None;
+
+    for &server in dns_servers {
+        match exchange(socket, server, name, qtype, options).await {
+            Ok(exchange) if exchange.response.header.result_code.is_server_failure() => {
+                warn!(
+                    "lookup of {} with ns {} failed: {}",
+                    name, server, exchange.response.header.result_code
+                );
+                last_failure = Some(exchange);
+            }
+            Ok(exchange) => return Ok(exchange),
+            Err(e) => {
+                warn!("lookup of {} with ns {} failed: {:#}", name, server, e);
+                last_error = e;
+            }
         }
     }
+
+    last_failure.ok_or(last_error)
 }
 
+/// Query `dns_server` over UDP, retrying over TCP if the answer was truncated.
+///
+/// Unanswered queries are resent up to `options.retries` times, doubling the
+/// timeout after every attempt.
 pub async fn lookup(
     socket: &UdpSocket,
     dns_server: impl ToSocketAddrs,
-    name: &String,
+    name: &Name,
     qtype: &QueryType,
+    options: &LookupOptions,
 ) -> Result<DnsPacket> {
-    send_request(socket, dns_server, name, qtype).await?;
-    get_response(socket).await
+    let exchange = exchange(socket, dns_server, name, qtype, options).await?;
+    Ok(exchange.response)
 }
 
-async fn send_request(
+/// Like `lookup`, but also report how the response was obtained.
+pub async fn exchange(
     socket: &UdpSocket,
     dns_server: impl ToSocketAddrs,
-    name: &String,
+    name: &Name,
     qtype: &QueryType,
-) -> Result<()> {
+    options: &LookupOptions,
+) -> Result<Exchange> {
+    let server = resolve(dns_server).await?;
+    let target = reachable_addr(socket, server)?;
+    let mut query = query_packet(name, qtype, options);
+    let start = Instant::now();
+
+    let (mut response, mut size) = udp_exchange(socket, target, &mut query, options).await?;
+    if rejects_edns(&query, &response) {
+        query.edns = None;
+        (response, size) = udp_exchange(socket, target, &mut query, options).await?;
+    }
+
+    if response.header.truncated_message {
+        let mut exchange = tcp_exchange(server, query, options).await?;
+        exchange.elapsed = start.elapsed();
+        return Ok(exchange);
+    }
+
+    Ok(Exchange {
+        response,
+        server,
+        size,
+        elapsed: start.elapsed(),
+        tcp: false,
+    })
+}
+
+/// Send `query` to `target` over UDP until it is answered or we run out of retries.
+async fn udp_exchange(
+    socket: &UdpSocket,
+    target: SocketAddr,
+    query: &mut DnsPacket,
+    options: &LookupOptions,
+) -> Result<(DnsPacket, usize)> {
+    let mut attempt_timeout = options.timeout;
+    let mut attempt = 0;
+
+    loop {
+        // Resending the same query lets a late answer to an earlier attempt match.
+        send_request(socket, target, query).await?;
+        match get_response(socket, target, query, attempt_timeout).await {
+            Ok(response) => return Ok(response),
+            Err(_) if attempt < options.retries => {
+                attempt += 1;
+                attempt_timeout *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether `response` is a server rejecting the OPT record of `query`, in
+/// which case the query should be resent without it (RFC 6891 section 7).
+fn rejects_edns(query: &DnsPacket, response: &DnsPacket) -> bool {
+    query.edns.is_some() && response.header.result_code == ResultCode::FORMERR
+}
+
+/// Query `dns_server` over TCP.
+pub async fn tcp_lookup(
+    dns_server: impl ToSocketAddrs,
+    name: &Name,
+    qtype: &QueryType,
+    options: &LookupOptions,
+) -> Result<DnsPacket> {
+    let server = resolve(dns_server).await?;
+    let mut query = query_packet(name, qtype, options);
+
+    let mut exchange = tcp_exchange(server, query.clone(), options).await?;
+    if rejects_edns(&query, &exchange.response) {
+        query.edns = None;
+        exchange = tcp_exchange(server, query, options).await?;
+    }
+    Ok(exchange.response)
+}
+
+async fn tcp_exchange(
+    server: SocketAddr,
+    mut query: DnsPacket,
+    options: &LookupOptions,
+) -> Result<Exchange> {
+    let mut req_buffer = BytePacketBuffer::new();
+    query.write(&mut req_buffer)?;
+    let start = Instant::now();
+
+    timeout(options.timeout, async {
+        let mut stream = TcpStream::connect(server)
+            .await
+            .context("Failed to connect to DNS server")?;
+
+        write_tcp_message(&mut stream, req_buffer.as_bytes()).await?;
+
+        let message = read_tcp_message(&mut stream).await?;
+        let response = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&message))?;
+        if !response.is_response_to(&query) {
+            return Err(anyhow!("Response does not match the query"));
+        }
+
+        Ok(Exchange {
+            response,
+            server,
+            size: message.len(),
+            elapsed: start.elapsed(),
+            tcp: true,
+        })
+    })
+    .await
+    .context("Timed out waiting for response from DNS server")?
+}
+
+async fn resolve(dns_server: impl ToSocketAddrs) -> Result<SocketAddr> {
+    lookup_host(dns_server)
+        .await
+        .context("Failed to resolve DNS server address")?
+        .next()
+        .context("DNS server address did not resolve")
+}
+
+/// Map `server` to an address `socket` can send to.
+///
+/// IPv4 servers are reached from a dual-stack socket through their
+/// IPv4-mapped IPv6 address, which is also where their responses come from.
+fn reachable_addr(socket: &UdpSocket, server: SocketAddr) -> Result<SocketAddr> {
+    let local = socket
+        .local_addr()
+        .context("Failed to get local socket address")?;
+
+    match (local, server) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => Ok(SocketAddr::new(
+            IpAddr::V6(v4.ip().to_ipv6_mapped()),
+            v4.port(),
+        )),
+        (SocketAddr::V4(_), SocketAddr::V6(_)) => Err(anyhow!(
+            "Cannot reach IPv6 server {} from an IPv4 socket",
+            server
+        )),
+        _ => Ok(server),
+    }
+}
+
+fn query_packet(name: &Name, qtype: &QueryType, options: &LookupOptions) -> DnsPacket {
     let mut packet = DnsPacket::new();
-    packet.header.id = 6666;
+    packet.header.id = rand::random();
     packet.header.question_count = 1;
-    packet.header.recursion_desired = true;
+    packet.header.recursion_desired = options.recursion_desired;
+    packet.header.checking_disabled = options.checking_disabled;
     packet
         .questions
         .push(DnsQuestion::new(name.clone(), *qtype));
+
+    let mut edns = Edns::new();
+    edns.payload_size = options.payload_size;
+    edns.dnssec_ok = options.dnssec_ok;
+    packet.edns = Some(edns);
+
+    packet
+}
+
+async fn send_request(
+    socket: &UdpSocket,
+    dns_server: SocketAddr,
+    query: &mut DnsPacket,
+) -> Result<()> {
     let mut req_buffer = BytePacketBuffer::new();
-    packet.write(&mut req_buffer)?;
+    query.write(&mut req_buffer)?;
 
     socket
-        .send_to(&req_buffer.buffer[0..req_buffer.position], dns_server)
+        .send_to(req_buffer.as_bytes(), dns_server)
         .await
         .context("Failed to send request to DNS server")?;
 
     Ok(())
 }
 
-async fn get_response(socket: &UdpSocket) -> Result<DnsPacket> {
-    let mut res_buffer = BytePacketBuffer::new();
+/// Wait for the response to `query`, returning it along with its size.
+///
+/// Datagrams from other addresses, unparseable ones, and responses whose ID or
+/// question section differ from the query are discarded, since they may be
+/// spoofed or belong to an earlier query.
+async fn get_response(
+    socket: &UdpSocket,
+    dns_server: SocketAddr,
+    query: &DnsPacket,
+    response_timeout: Duration,
+) -> Result<(DnsPacket, usize)> {
+    timeout(response_timeout, async {
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
 
-    socket
-        .recv_from(&mut res_buffer.buffer)
-        .await
-        .context("Failed to receive response from DNS server")?;
+        loop {
+            let (len, src) = socket
+                .recv_from(&mut buf)
+                .await
+                .context("Failed to receive response from DNS server")?;
 
-    DnsPacket::from_buffer(&mut res_buffer)
+            if src != dns_server {
+                continue;
+            }
+
+            let mut res_buffer = BytePacketBuffer::from_bytes(&buf[..len]);
+            match DnsPacket::from_buffer(&mut res_buffer) {
+                Ok(response) if response.is_response_to(query) => return Ok((response, len)),
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .context("Timed out waiting for response from DNS server")?
 }
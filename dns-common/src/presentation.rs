@@ -0,0 +1,172 @@
+//! Helpers for the text presentation format of RFC 1035 section 5.1.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+/// Split a line into its fields.
+///
+/// Fields are separated by whitespace, except inside quotes. Backslash escapes
+/// are kept in the field for the caller to decode, quotes are kept so quoted
+/// and unquoted character-strings can be told apart, and everything after an
+/// unquoted `;` is a comment. Parentheses only group lines, so they are dropped.
+pub(crate) fn tokenize(line: &str) -> Result<Vec<String>> {
+    tokenize_group(line).map(|(fields, _)| fields)
+}
+
+/// Like `tokenize`, but also return how many more parentheses the line opens
+/// than it closes, for joining an entry that spans several lines.
+pub(crate) fn tokenize_group(line: &str) -> Result<(Vec<String>, isize)> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut depth = 0;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                field.push(c);
+                field.push(chars.next().ok_or_else(|| anyhow!("Trailing backslash"))?);
+            }
+            '"' => {
+                field.push(c);
+                quoted = !quoted;
+            }
+            _ if quoted => field.push(c),
+            ';' => break,
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if c.is_whitespace() => {
+                if !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(anyhow!("Unterminated quoted string"));
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+
+    Ok((fields, depth))
+}
+
+/// Decode a `<character-string>`, quoted or not, into its bytes.
+pub(crate) fn parse_character_string(field: &str) -> Result<Vec<u8>> {
+    let field = match field.strip_prefix('"') {
+        Some(rest) => rest
+            .strip_suffix('"')
+            .ok_or_else(|| anyhow!("Unterminated quoted string"))?,
+        None => field,
+    };
+
+    let mut data = Vec::new();
+    let mut bytes = field.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            data.push(b);
+            continue;
+        }
+
+        data.push(read_escape(&mut bytes)?);
+    }
+
+    if data.len() > 0xFF {
+        return Err(anyhow!("Character string exceeds 255 bytes of length"));
+    }
+
+    Ok(data)
+}
+
+/// Decode the escape following a backslash: `\X` stands for the character X
+/// and `\DDD` for the byte with decimal value DDD.
+pub(crate) fn read_escape(bytes: &mut impl Iterator<Item = u8>) -> Result<u8> {
+    match bytes.next() {
+        Some(d) if d.is_ascii_digit() => {
+            let digits = [Some(d), bytes.next(), bytes.next()];
+            let value = digits.iter().try_fold(0u16, |acc, d| match d {
+                Some(d) if d.is_ascii_digit() => Some(acc * 10 + (d - b'0') as u16),
+                _ => None,
+            });
+            match value {
+                Some(value) if value <= 0xFF => Ok(value as u8),
+                _ => Err(anyhow!("Invalid decimal escape")),
+            }
+        }
+        Some(c) => Ok(c),
+        None => Err(anyhow!("Trailing backslash")),
+    }
+}
+
+/// Write `data` as a quoted `<character-string>`.
+pub(crate) fn write_character_string(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+    for &b in data {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Decode the hex digits of generic rdata, which may be split across fields.
+pub(crate) fn parse_hex(fields: &[String]) -> Result<Vec<u8>> {
+    let hex: String = fields.concat();
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|digits| digits.len() == 2 && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid hex digits"))
+        })
+        .collect()
+}
+
+/// Parse a TTL or other time value, either in seconds or with the units BIND
+/// accepts, such as `1h30m` (`s`, `m`, `h`, `d` and `w`, in any case).
+pub(crate) fn parse_ttl(field: &str) -> Result<u32> {
+    let invalid = || anyhow!("Invalid TTL {}", field);
+    if field.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total: u32 = 0;
+    let mut value: Option<u32> = None;
+    for c in field.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            let digits = value.unwrap_or(0).checked_mul(10);
+            value = Some(
+                digits
+                    .and_then(|v| v.checked_add(digit))
+                    .ok_or_else(invalid)?,
+            );
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let seconds = value.take().ok_or_else(invalid)?.checked_mul(unit);
+        total = seconds
+            .and_then(|s| total.checked_add(s))
+            .ok_or_else(invalid)?;
+    }
+
+    // A number without a unit counts seconds.
+    total.checked_add(value.unwrap_or(0)).ok_or_else(invalid)
+}
@@ -4,8 +4,16 @@ pub enum QueryType {
     A,
     NS,
     CNAME,
+    SOA,
+    PTR,
     MX,
+    TXT,
     AAAA,
+    SRV,
+    DS,
+    OPT,
+    SPF,
+    ANY,
 }
 
 impl QueryType {
@@ -14,8 +22,16 @@ impl QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
+            33 => QueryType::SRV,
+            43 => QueryType::DS,
+            41 => QueryType::OPT,
+            99 => QueryType::SPF,
+            255 => QueryType::ANY,
             _ => QueryType::Unknown(value),
         }
     }
@@ -25,13 +41,30 @@ impl QueryType {
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::DS => 43,
+            QueryType::OPT => 41,
+            QueryType::SPF => 99,
+            QueryType::ANY => 255,
             QueryType::Unknown(value) => value,
         }
     }
 }
 
+impl std::fmt::Display for QueryType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            QueryType::Unknown(value) => write!(f, "TYPE{}", value),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
 impl std::str::FromStr for QueryType {
     type Err = anyhow::Error;
 
@@ -40,9 +73,20 @@ impl std::str::FromStr for QueryType {
             "A" => Ok(QueryType::A),
             "NS" => Ok(QueryType::NS),
             "CNAME" => Ok(QueryType::CNAME),
+            "SOA" => Ok(QueryType::SOA),
+            "PTR" => Ok(QueryType::PTR),
             "MX" => Ok(QueryType::MX),
+            "TXT" => Ok(QueryType::TXT),
             "AAAA" => Ok(QueryType::AAAA),
-            _ => Err(anyhow::anyhow!("Unknown query type")),
+            "SRV" => Ok(QueryType::SRV),
+            "DS" => Ok(QueryType::DS),
+            "SPF" => Ok(QueryType::SPF),
+            "ANY" => Ok(QueryType::ANY),
+            // The generic TYPEnnn form of RFC 3597 section 5.
+            _ => match s.strip_prefix("TYPE").and_then(|n| n.parse().ok()) {
+                Some(value) => Ok(QueryType::from_u16(value)),
+                None => Err(anyhow::anyhow!("Unknown query type")),
+            },
         }
     }
 }
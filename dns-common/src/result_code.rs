@@ -1,22 +1,84 @@
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ResultCode {
-    NOERROR = 0,
-    FORMERR = 1,
-    SERVFAIL = 2,
-    NXDOMAIN = 3,
-    NOTIMP = 4,
-    REFUSED = 5,
+    /// A code we do not know, kept as is so it is never mistaken for success.
+    Unknown(u16),
+    NOERROR,
+    FORMERR,
+    SERVFAIL,
+    NXDOMAIN,
+    NOTIMP,
+    REFUSED,
+    YXDOMAIN,
+    YXRRSET,
+    NXRRSET,
+    NOTAUTH,
+    NOTZONE,
+    BADVERS,
+    BADCOOKIE,
 }
 
 impl ResultCode {
+    /// Decode the 4-bit result code carried in the header.
     pub fn from_u8(value: u8) -> Self {
+        ResultCode::from_u16((value & 0x0F) as u16)
+    }
+
+    /// Decode a 12-bit result code, i.e. the header code merged with the
+    /// extended bits of an OPT record.
+    pub fn from_u16(value: u16) -> Self {
         match value {
+            0 => ResultCode::NOERROR,
             1 => ResultCode::FORMERR,
             2 => ResultCode::SERVFAIL,
             3 => ResultCode::NXDOMAIN,
             4 => ResultCode::NOTIMP,
             5 => ResultCode::REFUSED,
-            0 | _ => ResultCode::NOERROR,
+            6 => ResultCode::YXDOMAIN,
+            7 => ResultCode::YXRRSET,
+            8 => ResultCode::NXRRSET,
+            9 => ResultCode::NOTAUTH,
+            10 => ResultCode::NOTZONE,
+            16 => ResultCode::BADVERS,
+            23 => ResultCode::BADCOOKIE,
+            _ => ResultCode::Unknown(value),
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            ResultCode::Unknown(value) => value,
+            ResultCode::NOERROR => 0,
+            ResultCode::FORMERR => 1,
+            ResultCode::SERVFAIL => 2,
+            ResultCode::NXDOMAIN => 3,
+            ResultCode::NOTIMP => 4,
+            ResultCode::REFUSED => 5,
+            ResultCode::YXDOMAIN => 6,
+            ResultCode::YXRRSET => 7,
+            ResultCode::NXRRSET => 8,
+            ResultCode::NOTAUTH => 9,
+            ResultCode::NOTZONE => 10,
+            ResultCode::BADVERS => 16,
+            ResultCode::BADCOOKIE => 23,
+        }
+    }
+
+    /// Whether the code says the server could not answer, rather than
+    /// anything about the name, so another server might.
+    pub fn is_server_failure(&self) -> bool {
+        matches!(
+            self,
+            ResultCode::SERVFAIL | ResultCode::REFUSED | ResultCode::FORMERR
+        )
+    }
+}
+
+impl std::fmt::Display for ResultCode {
+    /// Write the mnemonic of the code, or `RCODE<n>` for codes we do not know.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResultCode::Unknown(value) => write!(f, "RCODE{}", value),
+            _ => write!(f, "{:?}", self),
         }
     }
 }
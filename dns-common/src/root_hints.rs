@@ -0,0 +1,97 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::Result;
+use tokio::net::UdpSocket;
+
+use crate::{lookup_any, DnsRecord, LookupOptions, Name, QueryType};
+
+/// A root name server as listed in the IANA root hints file (named.root).
+#[derive(Debug, Clone, Copy)]
+pub struct RootHint {
+    pub name: &'static str,
+    pub ipv4: Ipv4Addr,
+    pub ipv6: Ipv6Addr,
+}
+
+const fn hint(name: &'static str, ipv4: [u8; 4], ipv6: [u16; 8]) -> RootHint {
+    RootHint {
+        name,
+        ipv4: Ipv4Addr::new(ipv4[0], ipv4[1], ipv4[2], ipv4[3]),
+        ipv6: Ipv6Addr::new(
+            ipv6[0], ipv6[1], ipv6[2], ipv6[3], ipv6[4], ipv6[5], ipv6[6], ipv6[7],
+        ),
+    }
+}
+
+#[rustfmt::skip]
+pub const ROOT_HINTS: [RootHint; 13] = [
+    hint("a.root-servers.net", [198, 41, 0, 4], [0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30]),
+    hint("b.root-servers.net", [170, 247, 170, 2], [0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb]),
+    hint("c.root-servers.net", [192, 33, 4, 12], [0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc]),
+    hint("d.root-servers.net", [199, 7, 91, 13], [0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd]),
+    hint("e.root-servers.net", [192, 203, 230, 10], [0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe]),
+    hint("f.root-servers.net", [192, 5, 5, 241], [0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf]),
+    hint("g.root-servers.net", [192, 112, 36, 4], [0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d]),
+    hint("h.root-servers.net", [198, 97, 190, 53], [0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53]),
+    hint("i.root-servers.net", [192, 36, 148, 17], [0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53]),
+    hint("j.root-servers.net", [192, 58, 128, 30], [0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30]),
+    hint("k.root-servers.net", [193, 0, 14, 129], [0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1]),
+    hint("l.root-servers.net", [199, 7, 83, 42], [0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42]),
+    hint("m.root-servers.net", [202, 12, 27, 33], [0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35]),
+];
+
+/// The addresses of the built-in root servers, IPv4 first.
+pub fn root_servers() -> Vec<IpAddr> {
+    let ipv4 = ROOT_HINTS.iter().map(|hint| IpAddr::V4(hint.ipv4));
+    let ipv6 = ROOT_HINTS.iter().map(|hint| IpAddr::V6(hint.ipv6));
+    ipv4.chain(ipv6).collect()
+}
+
+/// Ask the built-in root servers for the current root server set (RFC 8109).
+///
+/// Falls back to the built-in addresses if the response carries no usable glue.
+pub async fn prime_root_servers(
+    socket: &UdpSocket,
+    options: &LookupOptions,
+) -> Result<Vec<IpAddr>> {
+    let servers: Vec<SocketAddr> = root_servers()
+        .into_iter()
+        .map(|ip| (ip, 53).into())
+        .collect();
+    let options = LookupOptions {
+        recursion_desired: false,
+        ..options.clone()
+    };
+    let response = lookup_any(socket, &servers, &Name::root(), &QueryType::NS, &options).await?;
+
+    let names: Vec<&Name> = response
+        .answers
+        .iter()
+        .filter_map(|r| match r {
+            DnsRecord::NS {
+                domain,
+                name_server,
+                ..
+            } if domain.is_root() => Some(name_server),
+            _ => None,
+        })
+        .collect();
+
+    let mut primed: Vec<IpAddr> = response
+        .resources
+        .iter()
+        .filter(|r| names.contains(&r.domain()))
+        .filter_map(|r| match r {
+            DnsRecord::A { address, .. } => Some(IpAddr::V4(*address)),
+            DnsRecord::AAAA { address, .. } => Some(IpAddr::V6(*address)),
+            _ => None,
+        })
+        .collect();
+    primed.sort_by_key(|ip| ip.is_ipv6());
+
+    if primed.is_empty() {
+        return Ok(root_servers());
+    }
+
+    Ok(primed)
+}
@@ -0,0 +1,191 @@
+//! Serialization of messages in the JSON-friendly schema of RFC 8427.
+//!
+//! Names are written in presentation format, types and classes both as
+//! numbers and as mnemonics, and every record carries its rdata in hex
+//! alongside an `rdata<TYPE>` member holding its presentation format. The OPT
+//! pseudo-record is listed with the additional records like RFC 8427 does, and
+//! decoded once more in an `EDNS` member.
+
+use serde::ser::{Error, SerializeMap};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    BytePacketBuffer, DnsClass, DnsHeader, DnsPacket, DnsQuestion, DnsRecord, Edns, EdnsOption,
+    Name, QueryType, ResultCode, MAX_MESSAGE_SIZE,
+};
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Serialize for QueryType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.to_u16())
+    }
+}
+
+impl Serialize for DnsClass {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.to_u16())
+    }
+}
+
+impl Serialize for ResultCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.to_u16())
+    }
+}
+
+/// The counts of the four sections of a message.
+struct Counts {
+    questions: usize,
+    answers: usize,
+    authorities: usize,
+    additionals: usize,
+}
+
+fn serialize_header<M: SerializeMap>(
+    map: &mut M,
+    header: &DnsHeader,
+    counts: Counts,
+) -> Result<(), M::Error> {
+    map.serialize_entry("ID", &header.id)?;
+    map.serialize_entry("QR", &header.response)?;
+    map.serialize_entry("Opcode", &header.opcode)?;
+    map.serialize_entry("AA", &header.authoritative_answer)?;
+    map.serialize_entry("TC", &header.truncated_message)?;
+    map.serialize_entry("RD", &header.recursion_desired)?;
+    map.serialize_entry("RA", &header.recursion_available)?;
+    map.serialize_entry("AD", &header.authenticated_data)?;
+    map.serialize_entry("CD", &header.checking_disabled)?;
+    map.serialize_entry("RCODE", &header.result_code)?;
+    map.serialize_entry("RCODEname", &header.result_code.to_string())?;
+    map.serialize_entry("QDCOUNT", &counts.questions)?;
+    map.serialize_entry("ANCOUNT", &counts.answers)?;
+    map.serialize_entry("NSCOUNT", &counts.authorities)?;
+    map.serialize_entry("ARCOUNT", &counts.additionals)
+}
+
+impl Serialize for DnsHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let counts = Counts {
+            questions: self.question_count as usize,
+            answers: self.answer_count as usize,
+            authorities: self.authoritative_entry_count as usize,
+            additionals: self.resource_entry_count as usize,
+        };
+
+        let mut map = serializer.serialize_map(None)?;
+        serialize_header(&mut map, self, counts)?;
+        map.end()
+    }
+}
+
+impl Serialize for DnsPacket {
+    /// The counts are taken from the sections rather than the header, which
+    /// is only brought up to date when the packet is written.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let opt = self.edns.clone().map(|edns| DnsRecord::OPT { edns });
+        let additionals: Vec<&DnsRecord> = self.resources.iter().chain(&opt).collect();
+        let counts = Counts {
+            questions: self.questions.len(),
+            answers: self.answers.len(),
+            authorities: self.authorities.len(),
+            additionals: additionals.len(),
+        };
+
+        let mut map = serializer.serialize_map(None)?;
+        serialize_header(&mut map, &self.header, counts)?;
+        map.serialize_entry("questionRRs", &self.questions)?;
+        map.serialize_entry("answerRRs", &self.answers)?;
+        map.serialize_entry("authorityRRs", &self.authorities)?;
+        map.serialize_entry("additionalRRs", &additionals)?;
+        if let Some(edns) = &self.edns {
+            map.serialize_entry("EDNS", edns)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for DnsQuestion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("NAME", &self.qname)?;
+        map.serialize_entry("TYPE", &self.qtype)?;
+        map.serialize_entry("TYPEname", &self.qtype.to_string())?;
+        map.serialize_entry("CLASS", &self.qclass)?;
+        map.serialize_entry("CLASSname", &self.qclass.to_string())?;
+        map.end()
+    }
+}
+
+impl Serialize for DnsRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let qtype = self.query_type();
+        let rdata = wire_rdata(self).map_err(S::Error::custom)?;
+        let hex: String = rdata.iter().map(|b| format!("{:02X}", b)).collect();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("NAME", self.domain())?;
+        map.serialize_entry("TYPE", &qtype)?;
+        map.serialize_entry("TYPEname", &qtype.to_string())?;
+        match self {
+            // The class and TTL of the OPT pseudo-record carry the EDNS
+            // parameters instead (RFC 6891 section 6.1.3).
+            DnsRecord::OPT { edns } => {
+                let ttl = u32::from(edns.extended_rcode) << 24
+                    | u32::from(edns.version) << 16
+                    | u32::from(edns.dnssec_ok) << 15;
+                map.serialize_entry("CLASS", &edns.payload_size)?;
+                map.serialize_entry("TTL", &ttl)?;
+            }
+            _ => {
+                let class = self.class().unwrap_or_default();
+                map.serialize_entry("CLASS", &class)?;
+                map.serialize_entry("CLASSname", &class.to_string())?;
+                map.serialize_entry("TTL", &self.ttl())?;
+            }
+        }
+        map.serialize_entry("RDLENGTH", &rdata.len())?;
+        map.serialize_entry("RDATAHEX", &hex)?;
+        if !matches!(self, DnsRecord::UNKNOWN { .. } | DnsRecord::OPT { .. }) {
+            map.serialize_entry(&format!("rdata{}", qtype), &self.rdata().to_string())?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Edns {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("version", &self.version)?;
+        map.serialize_entry("DO", &self.dnssec_ok)?;
+        map.serialize_entry("udpPayloadSize", &self.payload_size)?;
+        map.serialize_entry("options", &self.options)?;
+        map.end()
+    }
+}
+
+impl Serialize for EdnsOption {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = self.data.iter().map(|b| format!("{:02X}", b)).collect();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("CODE", &self.code)?;
+        map.serialize_entry("DATAHEX", &hex)?;
+        map.end()
+    }
+}
+
+/// The uncompressed wire format rdata of `record`.
+fn wire_rdata(record: &DnsRecord) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = BytePacketBuffer::with_max_size(MAX_MESSAGE_SIZE);
+    buffer.set_compression(false);
+    record.write(&mut buffer)?;
+
+    // Skip the owner name, type, class, TTL and rdata length.
+    let start = record.domain().wire_len() + 10;
+    Ok(buffer.as_bytes()[start..].to_vec())
+}
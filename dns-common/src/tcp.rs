@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::MAX_MESSAGE_SIZE;
+
+/// Read one DNS message framed by a two-byte length prefix (RFC 1035 section 4.2.2).
+pub async fn read_tcp_message(stream: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>> {
+    let len = stream
+        .read_u16()
+        .await
+        .context("Failed to read message length")?;
+
+    let mut message = vec![0; len as usize];
+    stream
+        .read_exact(&mut message)
+        .await
+        .context("Failed to read message")?;
+
+    Ok(message)
+}
+
+/// Write one DNS message prefixed by its two-byte length.
+pub async fn write_tcp_message(
+    stream: &mut (impl AsyncWrite + Unpin),
+    message: &[u8],
+) -> Result<()> {
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(anyhow!("Message exceeds {} bytes", MAX_MESSAGE_SIZE));
+    }
+
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    framed.extend_from_slice(message);
+
+    stream
+        .write_all(&framed)
+        .await
+        .context("Failed to write message")?;
+    stream.flush().await.context("Failed to write message")
+}
@@ -0,0 +1,414 @@
+//! Reading zone files in the master file format of RFC 1035 section 5.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::presentation::{parse_ttl, tokenize_group};
+use crate::{DnsClass, DnsRecord, Name, QueryType};
+
+/// Give up on `$INCLUDE`s nested deeper than this, which are likely a loop.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Read the records of the zone file at `path`, in which relative names are
+/// relative to `origin` until a `$ORIGIN` says otherwise.
+///
+/// `$INCLUDE`d files are found relative to the directory of the file that
+/// includes them.
+pub fn read_zone_file(path: impl AsRef<Path>, origin: &Name) -> Result<Vec<DnsRecord>> {
+    let mut parser = ZoneParser::new(origin);
+    parser.read_file(path.as_ref())?;
+    Ok(parser.records)
+}
+
+/// Parse the records of a zone file held in `text`, as `read_zone_file` does.
+///
+/// `$INCLUDE`d files are found relative to the current directory.
+pub fn parse_zone(text: &str, origin: &Name) -> Result<Vec<DnsRecord>> {
+    let mut parser = ZoneParser::new(origin);
+    parser.parse(text, Path::new("."))?;
+    Ok(parser.records)
+}
+
+/// The state carried from one entry of a zone file to the next.
+struct ZoneParser {
+    origin: Name,
+    /// The TTL set by `$TTL` for records that do not give their own (RFC 2308).
+    default_ttl: Option<u32>,
+    /// The owner, TTL and class of the previous record, which a record that
+    /// leaves them out inherits.
+    last_owner: Option<Name>,
+    last_ttl: Option<u32>,
+    last_class: DnsClass,
+    depth: usize,
+    records: Vec<DnsRecord>,
+}
+
+impl ZoneParser {
+    fn new(origin: &Name) -> Self {
+        ZoneParser {
+            origin: origin.clone(),
+            default_ttl: None,
+            last_owner: None,
+            last_ttl: None,
+            last_class: DnsClass::IN,
+            depth: 0,
+            records: Vec::new(),
+        }
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read zone file {}", path.display()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        self.parse(&text, dir)
+            .with_context(|| format!("Invalid zone file {}", path.display()))
+    }
+
+    /// Parse the entries of `text`, an entry being a line or several lines
+    /// grouped by parentheses.
+    fn parse(&mut self, text: &str, dir: &Path) -> Result<()> {
+        let mut lines = text.lines().enumerate();
+
+        while let Some((number, line)) = lines.next() {
+            let (mut fields, mut depth) = tokenize_group(line)
+                .with_context(|| format!("Invalid entry at line {}", number + 1))?;
+            while depth > 0 {
+                let (_, next) = lines
+                    .next()
+                    .ok_or_else(|| anyhow!("Unbalanced parentheses at line {}", number + 1))?;
+                let (more, more_depth) = tokenize_group(next)
+                    .with_context(|| format!("Invalid entry at line {}", number + 1))?;
+                fields.extend(more);
+                depth += more_depth;
+            }
+            if depth < 0 {
+                return Err(anyhow!("Unbalanced parentheses at line {}", number + 1));
+            }
+            if fields.is_empty() {
+                continue;
+            }
+
+            // An entry that starts with blanks belongs to the previous owner.
+            let inherits_owner = line.starts_with([' ', '\t']);
+            self.parse_entry(&fields, inherits_owner, dir)
+                .with_context(|| format!("Invalid entry at line {}", number + 1))?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_entry(&mut self, fields: &[String], inherits_owner: bool, dir: &Path) -> Result<()> {
+        match fields[0].to_ascii_uppercase().as_str() {
+            "$ORIGIN" => {
+                let [_, origin] = fields else {
+                    return Err(anyhow!("Expected $ORIGIN <domain-name>"));
+                };
+                self.origin = Name::from_str_relative(origin, &self.origin)?;
+                Ok(())
+            }
+            "$TTL" => {
+                let [_, ttl] = fields else {
+                    return Err(anyhow!("Expected $TTL <ttl>"));
+                };
+                self.default_ttl = Some(parse_ttl(ttl)?);
+                Ok(())
+            }
+            "$INCLUDE" => {
+                let (file, origin) = match fields {
+                    [_, file] => (file, self.origin.clone()),
+                    [_, file, origin] => (file, Name::from_str_relative(origin, &self.origin)?),
+                    _ => return Err(anyhow!("Expected $INCLUDE <file-name> [<domain-name>]")),
+                };
+                self.include(&dir.join(file), origin)
+            }
+            directive if directive.starts_with('$') => {
+                Err(anyhow!("Unsupported directive {}", fields[0]))
+            }
+            _ => self.parse_record(fields, inherits_owner),
+        }
+    }
+
+    /// Read the records of an included file. The origin it starts with only
+    /// applies within it, as do its own `$ORIGIN`s (RFC 1035 section 5.1).
+    fn include(&mut self, path: &Path, origin: Name) -> Result<()> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(anyhow!("$INCLUDEs nested too deep"));
+        }
+
+        let parent_origin = std::mem::replace(&mut self.origin, origin);
+        self.depth += 1;
+        let result = self.read_file(path);
+        self.depth -= 1;
+        self.origin = parent_origin;
+        result
+    }
+
+    /// Parse `[<domain-name>] [<TTL>] [<class>] <type> <RDATA>`, where the TTL
+    /// and class may come in either order.
+    fn parse_record(&mut self, fields: &[String], inherits_owner: bool) -> Result<()> {
+        let mut fields = fields.iter();
+
+        let owner = if inherits_owner {
+            self.last_owner
+                .clone()
+                .ok_or_else(|| anyhow!("Missing owner name"))?
+        } else {
+            let field = fields.next().ok_or_else(|| anyhow!("Missing owner name"))?;
+            Name::from_str_relative(field, &self.origin)?
+        };
+
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let field = fields
+                .next()
+                .ok_or_else(|| anyhow!("Missing record type"))?;
+            let upper = field.to_ascii_uppercase();
+            if ttl.is_none() && field.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(field)?);
+            } else if class.is_none() && upper.parse::<DnsClass>().is_ok() {
+                class = upper.parse().ok();
+            } else {
+                break upper.parse::<QueryType>()?;
+            }
+        };
+
+        let class = class.unwrap_or(self.last_class);
+        let ttl = ttl
+            .or(self.default_ttl)
+            .or(self.last_ttl)
+            .ok_or_else(|| anyhow!("Missing TTL and no $TTL to default to"))?;
+
+        let rdata: Vec<String> = fields.cloned().collect();
+        let record = DnsRecord::from_rdata(owner.clone(), qtype, class, ttl, &rdata, &self.origin)
+            .with_context(|| format!("Invalid {} record", qtype))?;
+
+        self.last_owner = Some(owner);
+        self.last_ttl = Some(ttl);
+        self.last_class = class;
+        self.records.push(record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn parse(text: &str) -> Result<Vec<DnsRecord>> {
+        parse_zone(text, &name("example.com"))
+    }
+
+    fn a(domain: &str, address: Ipv4Addr, ttl: u32) -> DnsRecord {
+        DnsRecord::A {
+            domain: name(domain),
+            address,
+            class: DnsClass::IN,
+            ttl,
+        }
+    }
+
+    #[test]
+    fn origin_and_ttl_directives() {
+        let records = parse(
+            "$TTL 1h\n\
+             www A 192.0.2.1\n\
+             $ORIGIN sub.example.com.\n\
+             host 300 A 192.0.2.2\n\
+             @ A 192.0.2.3\n\
+             abs.example.org. A 192.0.2.4\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            records,
+            vec![
+                a("www.example.com", Ipv4Addr::new(192, 0, 2, 1), 3600),
+                a("host.sub.example.com", Ipv4Addr::new(192, 0, 2, 2), 300),
+                a("sub.example.com", Ipv4Addr::new(192, 0, 2, 3), 3600),
+                a("abs.example.org", Ipv4Addr::new(192, 0, 2, 4), 3600),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_without_ttl_inherit_the_last_one() {
+        let records = parse("www 600 A 192.0.2.1\nmail A 192.0.2.2\n").unwrap();
+        assert_eq!(records[1].ttl(), 600);
+
+        let err = parse("www A 192.0.2.1\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("Missing TTL"));
+    }
+
+    #[test]
+    fn parentheses_join_lines() {
+        let records = parse(
+            "@ 3600 IN SOA ns1 hostmaster.example.com. (\n\
+             \x20       2024010101 ; serial\n\
+             \x20       2h         ; refresh\n\
+             \x20       15m 1w\n\
+             \x20       1d )\n\
+             www A 192.0.2.1\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            records[0],
+            DnsRecord::SOA {
+                domain: name("example.com"),
+                mname: name("ns1.example.com"),
+                rname: name("hostmaster.example.com"),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 900,
+                expire: 604800,
+                minimum: 86400,
+                class: DnsClass::IN,
+                ttl: 3600,
+            }
+        );
+        assert_eq!(records[1].domain(), &name("www.example.com"));
+    }
+
+    #[test]
+    fn blank_owner_inherits_the_previous_one() {
+        let records = parse(
+            "$TTL 300\n\
+             www A 192.0.2.1\n\
+             \x20   AAAA 2001:db8::1\n\
+             \tTXT \"hello world\" two\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            records[1],
+            DnsRecord::AAAA {
+                domain: name("www.example.com"),
+                address: "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+                class: DnsClass::IN,
+                ttl: 300,
+            }
+        );
+        assert_eq!(
+            records[2],
+            DnsRecord::TXT {
+                domain: name("www.example.com"),
+                data: vec![b"hello world".to_vec(), b"two".to_vec()],
+                class: DnsClass::IN,
+                ttl: 300,
+            }
+        );
+    }
+
+    #[test]
+    fn generic_rdata() {
+        let records = parse(
+            "$TTL 300\n\
+             unknown TYPE65534 \\# 3 abcdef\n\
+             known A \\# 4 C0000201\n\
+             empty TYPE65535 \\# 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            records,
+            vec![
+                DnsRecord::UNKNOWN {
+                    domain: name("unknown.example.com"),
+                    qtype: 65534,
+                    class: DnsClass::IN,
+                    data: vec![0xab, 0xcd, 0xef],
+                    ttl: 300,
+                },
+                a("known.example.com", Ipv4Addr::new(192, 0, 2, 1), 300),
+                DnsRecord::UNKNOWN {
+                    domain: name("empty.example.com"),
+                    qtype: 65535,
+                    class: DnsClass::IN,
+                    data: Vec::new(),
+                    ttl: 300,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn include_applies_its_origin_only_within_the_file() {
+        let dir = std::env::temp_dir().join(format!("zone-file-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("example.zone"),
+            "$TTL 300\n\
+             www A 192.0.2.1\n\
+             $INCLUDE hosts.zone hosts\n\
+             mail A 192.0.2.3\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("hosts.zone"), "one A 192.0.2.2\n").unwrap();
+
+        let records = read_zone_file(dir.join("example.zone"), &name("example.com"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            records.unwrap(),
+            vec![
+                a("www.example.com", Ipv4Addr::new(192, 0, 2, 1), 300),
+                a("one.hosts.example.com", Ipv4Addr::new(192, 0, 2, 2), 300),
+                a("mail.example.com", Ipv4Addr::new(192, 0, 2, 3), 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected_with_their_line() {
+        let long_string = "x".repeat(256);
+        let cases = [
+            (
+                format!("$TTL 300\nwww TXT {}\n", long_string),
+                "line 2: Invalid TXT record: Character string exceeds 255 bytes",
+            ),
+            (
+                "$TTL 300\nwww A 192.0.2.1 (\n".to_string(),
+                "Unbalanced parentheses at line 2",
+            ),
+            (
+                "$TTL 300\nwww A 192.0.2.1 )\n".to_string(),
+                "Unbalanced parentheses at line 2",
+            ),
+            (
+                "$TTL 300\n  A 192.0.2.1\n".to_string(),
+                "line 2: Missing owner name",
+            ),
+            (
+                "$TTL 300\n$GENERATE 1-2 host$ A 192.0.2.$\n".to_string(),
+                "line 2: Unsupported directive $GENERATE",
+            ),
+            ("$TTL 1x\n".to_string(), "line 1: Invalid TTL 1x"),
+            (
+                "$TTL 300\nwww A 192.0.2\n".to_string(),
+                "line 2: Invalid A record",
+            ),
+            (
+                "$TTL 300\nwww BOGUS data\n".to_string(),
+                "line 2: Unknown query type",
+            ),
+            (
+                "$TTL 300\nwww TYPE65534 \\# 2 abcdef\n".to_string(),
+                "line 2: Invalid TYPE65534 record: Rdata length does not match its data",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let err = format!("{:#}", parse(&text).unwrap_err());
+            assert!(err.contains(expected), "{:?}: {}", text, err);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::{ForwardZone, ListenAddr, LogLevel, Mode, Upstream, ZoneFile};
+use crate::forwarder::Strategy;
+
+/// Flags override the settings of the config file, which override the defaults.
+#[derive(Parser)]
+pub struct Args {
+    /// TOML file to read settings from, with keys named after these flags
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+    /// Address to serve on as [udp:|tcp:]ADDR:PORT, both protocols without a prefix
+    /// [default: [::]:8080]
+    #[clap(short = 'l', long = "listen")]
+    pub listen: Vec<ListenAddr>,
+    /// Whether to iterate from the root servers (or the upstreams) or to forward
+    /// to the upstreams [default: iterate]
+    #[clap(short = 'm', long = "mode", value_enum)]
+    pub mode: Option<Mode>,
+    /// Upstream server as IP or IP:PORT; the servers to start iteration at
+    /// instead of the root servers, or to forward to
+    #[clap(short = 'u', long = "upstream")]
+    pub upstreams: Vec<Upstream>,
+    /// Which upstream to forward each question to first [default: sequential]
+    #[clap(long = "strategy", value_enum)]
+    pub strategy: Option<Strategy>,
+    /// Forward names in a zone to upstreams of its own, as ZONE=UPSTREAM[,UPSTREAM...];
+    /// the deepest matching zone wins
+    #[clap(short = 'f', long = "forward-zone")]
+    pub forward_zones: Vec<ForwardZone>,
+    /// Serve a zone authoritatively from a zone file, as ORIGIN=FILE
+    #[clap(short = 'z', long = "zone")]
+    pub zones: Vec<ZoneFile>,
+    /// Ask the built-in root servers for the current root server set at startup
+    #[clap(long = "prime")]
+    pub prime: bool,
+    /// Number of sockets to send upstream queries from [default: 10]
+    #[clap(long = "pool-size")]
+    pub pool_size: Option<usize>,
+    /// Seconds to wait for the first response from an upstream server [default: 2]
+    #[clap(long = "timeout")]
+    pub timeout: Option<u64>,
+    /// How many times to retry an upstream server before trying the next one
+    /// [default: 2]
+    #[clap(long = "retries")]
+    pub retries: Option<u32>,
+    /// Maximum number of answers to keep in the cache [default: 10000]
+    #[clap(long = "cache-size")]
+    pub cache_size: Option<usize>,
+    /// The most verbose messages to log [default: info]
+    #[clap(long = "log-level", value_enum)]
+    pub log_level: Option<LogLevel>,
+    /// Version string to answer CHAOS-class version.bind queries with
+    #[clap(long = "server-version")]
+    pub server_version: Option<String>,
+    /// Host name to answer CHAOS-class hostname.bind queries with
+    #[clap(long = "hostname")]
+    pub hostname: Option<String>,
+    /// Server identifier to answer CHAOS-class id.server queries with
+    #[clap(long = "server-id")]
+    pub server_id: Option<String>,
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dns_common::{DnsPacket, DnsQuestion, DnsRecord, ResultCode};
+
+/// Upper bound on how long any answer is kept, regardless of its TTL.
+const MAX_TTL: u32 = 86400;
+
+struct Entry {
+    result_code: ResultCode,
+    answers: Vec<DnsRecord>,
+    authorities: Vec<DnsRecord>,
+    resources: Vec<DnsRecord>,
+    inserted: Instant,
+    expires: Instant,
+}
+
+/// A size-bounded cache of resolved answers, keyed by question.
+pub struct Cache {
+    entries: Mutex<HashMap<DnsQuestion, Entry>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Look up a cached answer, with TTLs reduced by the time spent in the cache.
+    pub fn get(&self, question: &DnsQuestion) -> Option<DnsPacket> {
+        self.get_at(question, Instant::now())
+    }
+
+    fn get_at(&self, question: &DnsQuestion, now: Instant) -> Option<DnsPacket> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let entry = match entries.get(question) {
+            Some(entry) if entry.expires > now => entry,
+            Some(_) => {
+                entries.remove(question);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        let elapsed = now.duration_since(entry.inserted).as_secs() as u32;
+        let decrement = |records: &Vec<DnsRecord>| {
+            records
+                .iter()
+                .cloned()
+                .map(|mut rec| {
+                    rec.set_ttl(rec.ttl().saturating_sub(elapsed));
+                    rec
+                })
+                .collect()
+        };
+
+        let mut packet = DnsPacket::new();
+        packet.header.result_code = entry.result_code;
+        packet.answers = decrement(&entry.answers);
+        packet.authorities = decrement(&entry.authorities);
+        packet.resources = decrement(&entry.resources);
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(packet)
+    }
+
+    /// Store the answer to `question`.
+    ///
+    /// Positive answers live as long as their shortest TTL. NXDOMAIN and NODATA
+    /// answers live as long as the SOA in their authority section allows, and
+    /// are not cached without one (RFC 2308 section 5). Anything else, e.g.
+    /// SERVFAIL, is not cached.
+    pub fn insert(&self, question: DnsQuestion, response: &DnsPacket) {
+        self.insert_at(question, response, Instant::now())
+    }
+
+    fn insert_at(&self, question: DnsQuestion, response: &DnsPacket, now: Instant) {
+        let ttl = match response.header.result_code {
+            ResultCode::NOERROR if !response.answers.is_empty() => response
+                .answers
+                .iter()
+                .map(DnsRecord::ttl)
+                .min()
+                .unwrap_or(0),
+            ResultCode::NOERROR | ResultCode::NXDOMAIN => match response.negative_ttl() {
+                Some(ttl) => ttl,
+                None => return,
+            },
+            _ => return,
+        }
+        .min(MAX_TTL);
+
+        if ttl == 0 || self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(&question) {
+            entries.retain(|_, entry| entry.expires > now);
+        }
+
+        if entries.len() >= self.capacity && !entries.contains_key(&question) {
+            let soonest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(question, _)| question.clone());
+            if let Some(soonest) = soonest {
+                entries.remove(&soonest);
+            }
+        }
+
+        entries.insert(
+            question,
+            Entry {
+                result_code: response.header.result_code,
+                answers: response.answers.clone(),
+                authorities: response.authorities.clone(),
+                resources: response.resources.clone(),
+                inserted: now,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use dns_common::{DnsClass, Name, QueryType};
+
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn question(qname: &str) -> DnsQuestion {
+        DnsQuestion::new(name(qname), QueryType::A)
+    }
+
+    fn a(domain: &str, ttl: u32) -> DnsRecord {
+        DnsRecord::A {
+            domain: name(domain),
+            address: Ipv4Addr::new(192, 0, 2, 1),
+            class: DnsClass::IN,
+            ttl,
+        }
+    }
+
+    fn soa(ttl: u32, minimum: u32) -> DnsRecord {
+        DnsRecord::SOA {
+            domain: name("example.com"),
+            mname: name("ns1.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 1,
+            refresh: 7200,
+            retry: 900,
+            expire: 604800,
+            minimum,
+            class: DnsClass::IN,
+            ttl,
+        }
+    }
+
+    fn response(
+        result_code: ResultCode,
+        answers: Vec<DnsRecord>,
+        authorities: Vec<DnsRecord>,
+    ) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.result_code = result_code;
+        packet.answers = answers;
+        packet.authorities = authorities;
+        packet
+    }
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn ttls_count_down_until_the_shortest_expires() {
+        let cache = Cache::new(10);
+        let now = Instant::now();
+        let answers = vec![a("www.example.com", 300), a("www.example.com", 60)];
+        cache.insert_at(
+            question("www.example.com"),
+            &response(ResultCode::NOERROR, answers, vec![]),
+            now,
+        );
+
+        let cached = cache
+            .get_at(&question("www.example.com"), now + secs(10))
+            .unwrap();
+        assert_eq!(cached.header.result_code, ResultCode::NOERROR);
+        assert_eq!(
+            cached.answers,
+            vec![a("www.example.com", 290), a("www.example.com", 50)]
+        );
+
+        assert!(cache
+            .get_at(&question("www.example.com"), now + secs(60))
+            .is_none());
+        assert!(cache
+            .get_at(&question("www.example.com"), now + secs(10))
+            .is_none());
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+
+    #[test]
+    fn lookups_ignore_the_case_of_the_name() {
+        let cache = Cache::new(10);
+        let now = Instant::now();
+        let answers = vec![a("www.example.com", 300)];
+        cache.insert_at(
+            question("www.example.com"),
+            &response(ResultCode::NOERROR, answers, vec![]),
+            now,
+        );
+
+        assert!(cache.get_at(&question("WWW.Example.com"), now).is_some());
+    }
+
+    #[test]
+    fn ttls_are_capped() {
+        let cache = Cache::new(10);
+        let now = Instant::now();
+        let answers = vec![a("www.example.com", 7 * 86400)];
+        cache.insert_at(
+            question("www.example.com"),
+            &response(ResultCode::NOERROR, answers, vec![]),
+            now,
+        );
+
+        let expired = now + secs(MAX_TTL as u64);
+        assert!(cache
+            .get_at(&question("www.example.com"), expired)
+            .is_none());
+    }
+
+    #[test]
+    fn negative_answers_live_as_long_as_their_soa_allows() {
+        let cache = Cache::new(10);
+        let now = Instant::now();
+        cache.insert_at(
+            question("missing.example.com"),
+            &response(ResultCode::NXDOMAIN, vec![], vec![soa(3600, 300)]),
+            now,
+        );
+        cache.insert_at(
+            question("nodata.example.com"),
+            &response(ResultCode::NOERROR, vec![], vec![soa(120, 300)]),
+            now,
+        );
+
+        let cached = cache
+            .get_at(&question("missing.example.com"), now + secs(100))
+            .unwrap();
+        assert_eq!(cached.header.result_code, ResultCode::NXDOMAIN);
+        assert_eq!(cached.authorities, vec![soa(3500, 300)]);
+        assert!(cache
+            .get_at(&question("missing.example.com"), now + secs(300))
+            .is_none());
+
+        assert!(cache
+            .get_at(&question("nodata.example.com"), now + secs(100))
+            .is_some());
+        assert!(cache
+            .get_at(&question("nodata.example.com"), now + secs(120))
+            .is_none());
+    }
+
+    #[test]
+    fn failures_and_negative_answers_without_soa_are_not_cached() {
+        let cache = Cache::new(10);
+        let now = Instant::now();
+        let responses = [
+            response(ResultCode::NXDOMAIN, vec![], vec![]),
+            response(ResultCode::NOERROR, vec![], vec![]),
+            response(ResultCode::SERVFAIL, vec![], vec![]),
+            response(ResultCode::REFUSED, vec![a("www.example.com", 300)], vec![]),
+            response(ResultCode::NOERROR, vec![a("www.example.com", 0)], vec![]),
+        ];
+
+        for response in &responses {
+            cache.insert_at(question("www.example.com"), response, now);
+            assert!(cache.get_at(&question("www.example.com"), now).is_none());
+        }
+    }
+
+    #[test]
+    fn full_cache_drops_expired_entries_then_the_soonest_to_expire() {
+        let cache = Cache::new(2);
+        let now = Instant::now();
+        let insert = |qname: &str, ttl: u32, at: Instant| {
+            let answers = vec![a(qname, ttl)];
+            cache.insert_at(
+                question(qname),
+                &response(ResultCode::NOERROR, answers, vec![]),
+                at,
+            );
+        };
+
+        insert("short.example.com", 10, now);
+        insert("long.example.com", 600, now);
+        insert("medium.example.com", 300, now);
+        assert!(cache.get_at(&question("short.example.com"), now).is_none());
+        assert!(cache.get_at(&question("long.example.com"), now).is_some());
+        assert!(cache.get_at(&question("medium.example.com"), now).is_some());
+
+        // Once medium has expired, it makes room before anything else does.
+        let later = now + secs(400);
+        insert("new.example.com", 300, later);
+        assert!(cache.get_at(&question("long.example.com"), later).is_some());
+        assert!(cache.get_at(&question("new.example.com"), later).is_some());
+
+        // Replacing an entry does not evict another one.
+        insert("long.example.com", 60, later);
+        assert!(cache.get_at(&question("new.example.com"), later).is_some());
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = Cache::new(0);
+        let now = Instant::now();
+        let answers = vec![a("www.example.com", 300)];
+        cache.insert_at(
+            question("www.example.com"),
+            &response(ResultCode::NOERROR, answers, vec![]),
+            now,
+        );
+        assert!(cache.get_at(&question("www.example.com"), now).is_none());
+    }
+}
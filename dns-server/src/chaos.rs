@@ -0,0 +1,41 @@
+use dns_common::{DnsClass, DnsPacket, DnsQuestion, DnsRecord, QueryType, ResultCode};
+
+/// What the server reveals about itself through CHAOS-class TXT queries.
+#[derive(Default)]
+pub struct Identity {
+    pub version: Option<String>,
+    pub hostname: Option<String>,
+    pub id: Option<String>,
+}
+
+impl Identity {
+    /// Answer a CHAOS-class question; names that are not configured are refused.
+    pub fn answer(&self, question: &DnsQuestion, packet: &mut DnsPacket) {
+        let name = question.qname.to_string().to_ascii_lowercase();
+        let value = match name.as_str() {
+            "version.bind." | "version.server." => &self.version,
+            "hostname.bind." => &self.hostname,
+            "id.server." => &self.id,
+            _ => &None,
+        };
+
+        let value = match value {
+            Some(value) => value,
+            None => {
+                packet.header.result_code = ResultCode::REFUSED;
+                return;
+            }
+        };
+
+        packet.header.authoritative_answer = true;
+        packet.header.result_code = ResultCode::NOERROR;
+        if matches!(question.qtype, QueryType::TXT | QueryType::ANY) {
+            packet.answers.push(DnsRecord::TXT {
+                domain: question.qname.clone(),
+                data: value.as_bytes().chunks(255).map(|s| s.to_vec()).collect(),
+                class: DnsClass::CH,
+                ttl: 0,
+            });
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use dns_common::Name;
+
+use crate::args::Args;
+use crate::forwarder::Strategy;
+
+/// The port name servers listen on.
+const DNS_PORT: u16 = 53;
+
+/// The server configuration, read from a TOML file whose keys are named after
+/// the command line flags. Flags given on the command line take precedence.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub listen: Vec<ListenAddr>,
+    pub mode: Mode,
+    pub upstreams: Vec<Upstream>,
+    pub strategy: Strategy,
+    pub forward_zones: Vec<ForwardZone>,
+    pub zones: Vec<ZoneFile>,
+    pub prime: bool,
+    pub pool_size: usize,
+    pub cache_size: usize,
+    pub timeout: u64,
+    pub retries: u32,
+    pub log_level: LogLevel,
+    pub server_version: Option<String>,
+    pub hostname: Option<String>,
+    pub server_id: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            // Listen on all IPv6 addresses, which also accepts IPv4 on
+            // dual-stack hosts.
+            listen: vec![ListenAddr {
+                addr: "[::]:8080".parse().unwrap(),
+                protocol: Protocol::Both,
+            }],
+            mode: Mode::Iterate,
+            upstreams: Vec::new(),
+            strategy: Strategy::Sequential,
+            forward_zones: Vec::new(),
+            zones: Vec::new(),
+            prime: false,
+            pool_size: 10,
+            cache_size: 10000,
+            timeout: 2,
+            retries: 2,
+            log_level: LogLevel::Info,
+            server_version: None,
+            hostname: None,
+            server_id: None,
+        }
+    }
+}
+
+impl Config {
+    /// Build the configuration from the file named by `--config`, if any, and
+    /// the other flags, and check that it makes sense.
+    pub fn load(args: Args) -> Result<Config> {
+        let mut config = match &args.config {
+            Some(path) => Config::read(path)?,
+            None => Config::default(),
+        };
+        config.apply(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Override the file with the flags given on the command line.
+    fn apply(&mut self, args: Args) {
+        if !args.listen.is_empty() {
+            self.listen = args.listen;
+        }
+        if let Some(mode) = args.mode {
+            self.mode = mode;
+        }
+        if !args.upstreams.is_empty() {
+            self.upstreams = args.upstreams;
+        }
+        self.strategy = args.strategy.unwrap_or(self.strategy);
+        if !args.forward_zones.is_empty() {
+            self.forward_zones = args.forward_zones;
+        }
+        if !args.zones.is_empty() {
+            self.zones = args.zones;
+        }
+        self.prime |= args.prime;
+        self.pool_size = args.pool_size.unwrap_or(self.pool_size);
+        self.cache_size = args.cache_size.unwrap_or(self.cache_size);
+        self.timeout = args.timeout.unwrap_or(self.timeout);
+        self.retries = args.retries.unwrap_or(self.retries);
+        self.log_level = args.log_level.unwrap_or(self.log_level);
+        self.server_version = args.server_version.or(self.server_version.take());
+        self.hostname = args.hostname.or(self.hostname.take());
+        self.server_id = args.server_id.or(self.server_id.take());
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.listen.is_empty() {
+            return Err(anyhow!("No addresses to listen on"));
+        }
+
+        let mut bound = HashSet::new();
+        for listen in &self.listen {
+            for protocol in listen.protocol.split() {
+                if !bound.insert((listen.addr, protocol)) {
+                    return Err(anyhow!(
+                        "Listen address {} is given more than once for {}",
+                        listen.addr,
+                        protocol
+                    ));
+                }
+            }
+        }
+
+        if self.pool_size == 0 {
+            return Err(anyhow!("The socket pool size must be at least 1"));
+        }
+        if self.timeout == 0 {
+            return Err(anyhow!("The timeout must be at least 1 second"));
+        }
+
+        let mut zones = HashSet::new();
+        for forward_zone in &self.forward_zones {
+            if !zones.insert(&forward_zone.zone) {
+                return Err(anyhow!(
+                    "Forward zone {} is given more than once",
+                    forward_zone.zone
+                ));
+            }
+        }
+
+        let mut origins = HashSet::new();
+        for zone in &self.zones {
+            if !origins.insert(&zone.origin) {
+                return Err(anyhow!("Zone {} is given more than once", zone.origin));
+            }
+            if zones.contains(&zone.origin) {
+                return Err(anyhow!("Zone {} is both served and forwarded", zone.origin));
+            }
+        }
+
+        match self.mode {
+            Mode::Forward => {
+                if self.upstreams.is_empty() {
+                    return Err(anyhow!("Forward mode needs at least one upstream server"));
+                }
+                if self.prime {
+                    return Err(anyhow!("Priming only applies to iterate mode"));
+                }
+            }
+            Mode::Iterate => {
+                if let Some(upstream) = self.upstreams.iter().find(|u| u.addr.port() != DNS_PORT) {
+                    return Err(anyhow!(
+                        "Upstream {} must use port {} in iterate mode, which follows referrals to port {}",
+                        upstream,
+                        DNS_PORT,
+                        DNS_PORT
+                    ));
+                }
+                if self.prime && !self.upstreams.is_empty() {
+                    return Err(anyhow!(
+                        "Priming only applies when iterating from the root servers, not from upstreams"
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How the server answers questions it is not authoritative for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Resolve iteratively, starting at the upstreams or the root servers
+    Iterate,
+    /// Relay questions to the upstreams, which resolve them recursively
+    Forward,
+}
+
+/// The most verbose level of messages to log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for tracing::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+    Both,
+}
+
+impl Protocol {
+    /// The transports to listen on.
+    fn split(self) -> &'static [Protocol] {
+        match self {
+            Protocol::Udp => &[Protocol::Udp],
+            Protocol::Tcp => &[Protocol::Tcp],
+            Protocol::Both => &[Protocol::Udp, Protocol::Tcp],
+        }
+    }
+
+    pub fn udp(self) -> bool {
+        self != Protocol::Tcp
+    }
+
+    pub fn tcp(self) -> bool {
+        self != Protocol::Udp
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Udp => f.write_str("UDP"),
+            Protocol::Tcp => f.write_str("TCP"),
+            Protocol::Both => f.write_str("UDP and TCP"),
+        }
+    }
+}
+
+/// An address to serve on, written `[udp:|tcp:]ADDR:PORT`. Without a prefix
+/// the server listens on both UDP and TCP.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ListenAddr {
+    pub addr: SocketAddr,
+    pub protocol: Protocol,
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, addr) = if let Some(addr) = s.strip_prefix("udp:") {
+            (Protocol::Udp, addr)
+        } else if let Some(addr) = s.strip_prefix("tcp:") {
+            (Protocol::Tcp, addr)
+        } else {
+            (Protocol::Both, s)
+        };
+
+        let addr = addr.parse().with_context(|| {
+            format!(
+                "Invalid listen address {}, expected [udp:|tcp:]ADDR:PORT such as udp:[::1]:53",
+                s
+            )
+        })?;
+
+        Ok(ListenAddr { addr, protocol })
+    }
+}
+
+impl TryFrom<String> for ListenAddr {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// An upstream name server, written as an IP address with an optional port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Upstream {
+    pub addr: SocketAddr,
+}
+
+impl FromStr for Upstream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let addr = match s.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, DNS_PORT),
+            Err(_) => s.parse().with_context(|| {
+                format!(
+                    "Invalid upstream {}, expected an IP address with an optional port",
+                    s
+                )
+            })?,
+        };
+
+        Ok(Upstream { addr })
+    }
+}
+
+impl TryFrom<String> for Upstream {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.addr)
+    }
+}
+
+/// A zone whose names are forwarded to upstreams of its own, written
+/// `ZONE=UPSTREAM[,UPSTREAM...]` such as `corp.internal=10.0.0.53,10.0.1.53`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ForwardZone {
+    pub zone: Name,
+    pub upstreams: Vec<Upstream>,
+}
+
+impl FromStr for ForwardZone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (zone, upstreams) = s.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "Invalid forward zone {}, expected ZONE=UPSTREAM[,UPSTREAM...]",
+                s
+            )
+        })?;
+
+        // clap only shows the outermost error, so the causes go in the message.
+        let zone = zone
+            .parse()
+            .map_err(|e| anyhow!("Invalid forward zone {}: {:#}", s, e))?;
+        let upstreams = upstreams
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Upstream>>>()
+            .map_err(|e| anyhow!("Invalid forward zone {}: {:#}", s, e))?;
+
+        Ok(ForwardZone { zone, upstreams })
+    }
+}
+
+impl TryFrom<String> for ForwardZone {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A zone to serve authoritatively, written `ORIGIN=FILE` such as
+/// `example.com=/etc/dns/example.com.zone`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ZoneFile {
+    pub origin: Name,
+    pub path: PathBuf,
+}
+
+impl FromStr for ZoneFile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, path) = s
+            .split_once('=')
+            .filter(|(_, path)| !path.is_empty())
+            .ok_or_else(|| anyhow!("Invalid zone {}, expected ORIGIN=FILE", s))?;
+        let origin = origin
+            .parse()
+            .map_err(|e| anyhow!("Invalid zone {}: {:#}", s, e))?;
+
+        Ok(ZoneFile {
+            origin,
+            path: path.into(),
+        })
+    }
+}
+
+impl TryFrom<String> for ZoneFile {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
@@ -0,0 +1,240 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+use tracing::{debug, warn};
+
+use dns_common::{exchange, DnsQuestion, Exchange, LookupOptions};
+
+/// How a forwarder picks the upstream to send a question to first. The others
+/// are tried in turn if it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Always start with the first upstream
+    Sequential,
+    /// Start with the next upstream on every question, spreading the load
+    RoundRobin,
+    /// Start with the upstream that has been answering quickest
+    Fastest,
+}
+
+/// Sends questions with the RD flag set to a list of recursive resolvers and
+/// relays their answers.
+pub struct Forwarder {
+    servers: Vec<SocketAddr>,
+    strategy: Strategy,
+    /// The upstream round-robin starts at next.
+    next: AtomicUsize,
+    /// The smoothed response time of each upstream, unknown until it is used.
+    rtts: Mutex<Vec<Option<Duration>>>,
+}
+
+impl Forwarder {
+    pub fn new(servers: Vec<SocketAddr>, strategy: Strategy) -> Self {
+        let rtts = Mutex::new(vec![None; servers.len()]);
+        Forwarder {
+            servers,
+            strategy,
+            next: AtomicUsize::new(0),
+            rtts,
+        }
+    }
+
+    /// Forward `question` with the flags in `options` and return the first answer.
+    ///
+    /// Upstreams that answer SERVFAIL, REFUSED or FORMERR are skipped like
+    /// those that do not answer; the last such answer is only returned if
+    /// every upstream fails.
+    pub async fn forward(
+        &self,
+        socket: &UdpSocket,
+        question: &DnsQuestion,
+        options: &LookupOptions,
+    ) -> Result<Exchange> {
+        let options = LookupOptions {
+            recursion_desired: true,
+            ..options.clone()
+        };
+        // Count failures as the time we would wait in vain for an upstream
+        // that does not answer, so the fastest strategy moves away from
+        // upstreams that fail, however quickly they do.
+        let penalty = options.timeout * (options.retries + 1);
+        let mut last_error = anyhow!("No upstream servers to forward to");
+        let mut last_failure = None;
+
+        for i in self.order() {
+            let server = self.servers[i];
+            match exchange(socket, server, &question.qname, &question.qtype, &options).await {
+                Ok(exchange) if exchange.response.header.result_code.is_server_failure() => {
+                    self.record(i, penalty);
+                    warn!(
+                        "forwarding {} {} to {} failed: {}",
+                        question.qtype,
+                        question.qname,
+                        server,
+                        exchange.response.header.result_code
+                    );
+                    last_failure = Some(exchange);
+                }
+                Ok(exchange) => {
+                    self.record(i, exchange.elapsed);
+                    debug!(
+                        "forwarded {} {} to {} in {} ms: {}",
+                        question.qtype,
+                        question.qname,
+                        server,
+                        exchange.elapsed.as_millis(),
+                        exchange.response.header.result_code
+                    );
+                    return Ok(exchange);
+                }
+                Err(e) => {
+                    self.record(i, penalty);
+                    warn!(
+                        "forwarding {} {} to {} failed: {:#}",
+                        question.qtype, question.qname, server, e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        last_failure.ok_or(last_error)
+    }
+
+    /// The indices of the upstreams in the order to try them.
+    fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.servers.len()).collect();
+
+        match self.strategy {
+            Strategy::Sequential => {}
+            Strategy::RoundRobin if !order.is_empty() => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % order.len();
+                order.rotate_left(start);
+            }
+            Strategy::RoundRobin => {}
+            Strategy::Fastest => {
+                // Upstreams that have not been measured yet come first. The
+                // sort is stable, so ties keep the configured order.
+                let rtts = self.rtts.lock().unwrap();
+                order.sort_by_key(|&i| rtts[i]);
+            }
+        }
+
+        order
+    }
+
+    /// Fold a response time of upstream `i` into its smoothed response time.
+    ///
+    /// The others slowly decay, so an upstream that was slow once gets
+    /// another chance eventually.
+    fn record(&self, i: usize, sample: Duration) {
+        let mut rtts = self.rtts.lock().unwrap();
+
+        for (j, rtt) in rtts.iter_mut().enumerate() {
+            *rtt = match *rtt {
+                None if j == i => Some(sample),
+                Some(rtt) if j == i => Some(rtt * 7 / 8 + sample / 8),
+                Some(rtt) => Some(rtt * 63 / 64),
+                None => None,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarder(count: u16, strategy: Strategy) -> Forwarder {
+        let servers = (0..count)
+            .map(|i| SocketAddr::from(([127, 0, 0, 1], 5300 + i)))
+            .collect();
+        Forwarder::new(servers, strategy)
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn sequential_always_starts_with_the_first() {
+        let forwarder = forwarder(3, Strategy::Sequential);
+        forwarder.record(0, ms(500));
+        forwarder.record(2, ms(5));
+
+        assert_eq!(forwarder.order(), vec![0, 1, 2]);
+        assert_eq!(forwarder.order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn round_robin_starts_with_the_next_each_time() {
+        let forwarder = forwarder(3, Strategy::RoundRobin);
+
+        assert_eq!(forwarder.order(), vec![0, 1, 2]);
+        assert_eq!(forwarder.order(), vec![1, 2, 0]);
+        assert_eq!(forwarder.order(), vec![2, 0, 1]);
+        assert_eq!(forwarder.order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn no_upstreams_means_no_order() {
+        for strategy in [
+            Strategy::Sequential,
+            Strategy::RoundRobin,
+            Strategy::Fastest,
+        ] {
+            assert!(forwarder(0, strategy).order().is_empty());
+        }
+    }
+
+    #[test]
+    fn fastest_tries_unmeasured_upstreams_first() {
+        let forwarder = forwarder(4, Strategy::Fastest);
+        assert_eq!(forwarder.order(), vec![0, 1, 2, 3]);
+
+        forwarder.record(0, ms(10));
+        forwarder.record(2, ms(50));
+        assert_eq!(forwarder.order(), vec![1, 3, 0, 2]);
+
+        forwarder.record(1, ms(30));
+        forwarder.record(3, ms(30));
+        assert_eq!(forwarder.order(), vec![0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn fastest_follows_the_smoothed_response_time() {
+        let forwarder = forwarder(2, Strategy::Fastest);
+        forwarder.record(0, ms(80));
+        forwarder.record(1, ms(100));
+        assert_eq!(forwarder.order(), vec![0, 1]);
+
+        // One slow answer is smoothed out rather than taken at face value.
+        forwarder.record(0, ms(200));
+        assert_eq!(forwarder.order(), vec![0, 1]);
+
+        // A failure costs a full timeout and pushes the upstream back.
+        forwarder.record(0, Duration::from_secs(10));
+        assert_eq!(forwarder.order(), vec![1, 0]);
+    }
+
+    #[test]
+    fn fastest_lets_slow_upstreams_recover() {
+        let forwarder = forwarder(2, Strategy::Fastest);
+        forwarder.record(0, ms(400));
+        forwarder.record(1, ms(100));
+        assert_eq!(forwarder.order(), vec![1, 0]);
+
+        // Upstream 0 decays while upstream 1 keeps answering in 100 ms.
+        for _ in 0..200 {
+            forwarder.record(1, ms(100));
+        }
+        assert_eq!(forwarder.order(), vec![0, 1]);
+    }
+}
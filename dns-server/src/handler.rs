@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+use tub::Pool;
+
+use crate::cache::Cache;
+use crate::chaos::Identity;
+use crate::forwarder::Forwarder;
+use crate::zone::Zone;
+
+use dns_common::{
+    traced_recursive_lookup, BytePacketBuffer, DnsClass, DnsHeader, DnsPacket, DnsQuestion, Edns,
+    LookupOptions, Name, ResultCode, EDNS_PAYLOAD_SIZE, EDNS_VERSION, UDP_MESSAGE_SIZE,
+};
+
+/// Give up resolving a question after this long, so that clients get SERVFAIL
+/// before they stop waiting, which dig and most stub resolvers do after five
+/// seconds.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// The opcode of a standard query (RFC 1035 section 4.1.1).
+const OPCODE_QUERY: u8 = 0;
+
+/// Where the answers to questions come from.
+pub enum Upstreams {
+    /// Resolve iteratively, starting at these servers.
+    Iterate { servers: Vec<IpAddr> },
+    /// Relay questions to recursive resolvers.
+    Forward { forwarder: Forwarder },
+}
+
+/// Answers requests, independent of the transport they arrived on.
+pub struct Handler {
+    pool: Pool<UdpSocket>,
+    upstreams: Upstreams,
+    /// Zones whose names are forwarded to their own upstreams, whatever the
+    /// default path is.
+    forward_zones: HashMap<Name, Forwarder>,
+    /// Zones the server answers for itself, by origin.
+    zones: HashMap<Name, Zone>,
+    options: LookupOptions,
+    cache: Cache,
+    identity: Identity,
+}
+
+impl Handler {
+    pub fn new(
+        pool: Pool<UdpSocket>,
+        upstreams: Upstreams,
+        forward_zones: HashMap<Name, Forwarder>,
+        zones: HashMap<Name, Zone>,
+        options: LookupOptions,
+        cache: Cache,
+        identity: Identity,
+    ) -> Self {
+        Handler {
+            pool,
+            upstreams,
+            forward_zones,
+            zones,
+            options,
+            cache,
+            identity,
+        }
+    }
+
+    /// Build the response to a request.
+    pub async fn handle_request(&self, mut request: DnsPacket) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = request.header.id;
+        packet.header.opcode = request.header.opcode;
+        packet.header.recursion_desired = true;
+        packet.header.recursion_available = true;
+        packet.header.response = true;
+
+        if let Some(edns) = &request.edns {
+            let mut response_edns = Edns::new();
+            response_edns.dnssec_ok = edns.dnssec_ok;
+            packet.edns = Some(response_edns);
+        }
+
+        if request.header.opcode != OPCODE_QUERY {
+            // Only standard queries are supported, not NOTIFY, UPDATE and the like.
+            packet.questions = request.questions;
+            packet.header.result_code = ResultCode::NOTIMP;
+        } else if request
+            .edns
+            .as_ref()
+            .is_some_and(|edns| edns.version > EDNS_VERSION)
+        {
+            packet.header.result_code = ResultCode::BADVERS;
+        } else if let Some(question) = request.questions.pop() {
+            if question.qclass == DnsClass::CH {
+                self.identity.answer(&question, &mut packet);
+                packet.questions.push(question);
+            } else if question.qclass != DnsClass::IN {
+                packet.questions.push(question);
+                packet.header.result_code = ResultCode::NOTIMP;
+            } else if let Some(zone) = self.zone(&question.qname) {
+                zone.answer(&question, &mut packet);
+                packet.questions.push(question);
+            } else {
+                match self.resolve(&question, &request).await {
+                    Ok(result) => {
+                        packet.header.result_code = result.header.result_code;
+                        packet.header.authenticated_data = result.header.authenticated_data;
+
+                        for rec in result.answers {
+                            debug!("Answer: {:?}", rec);
+                            packet.answers.push(rec);
+                        }
+
+                        for rec in result.authorities {
+                            debug!("Authority: {:?}", rec);
+                            packet.authorities.push(rec);
+                        }
+
+                        for rec in result.resources {
+                            debug!("Resource: {:?}", rec);
+                            packet.resources.push(rec);
+                        }
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to resolve {} {}: {:#}",
+                            question.qtype, question.qname, e
+                        );
+                        packet.header.result_code = ResultCode::SERVFAIL;
+                    }
+                }
+                packet.questions.push(question);
+            }
+        } else {
+            packet.header.result_code = ResultCode::FORMERR;
+        }
+
+        packet
+    }
+
+    /// Answer `question` from the cache, or resolve it and cache the result.
+    ///
+    /// Names in a forward zone go to that zone's forwarder, everything else
+    /// takes the default path.
+    async fn resolve(&self, question: &DnsQuestion, request: &DnsPacket) -> Result<DnsPacket> {
+        let zone = self.forward_zone(&question.qname);
+        let options = match (zone, &self.upstreams) {
+            (None, Upstreams::Iterate { .. }) => self.options.clone(),
+            _ => self.forward_options(request),
+        };
+
+        // Answers to queries with DNSSEC flags differ from those to plain
+        // queries, so they bypass the cache.
+        let cacheable = !options.checking_disabled && !options.dnssec_ok;
+        if let Some(cached) = cacheable.then(|| self.cache.get(question)).flatten() {
+            debug!(
+                "cache hit for {:?} {} ({} hits, {} misses)",
+                question.qtype,
+                question.qname,
+                self.cache.hits(),
+                self.cache.misses()
+            );
+            return Ok(cached);
+        }
+
+        let resolution = async {
+            match (zone, &self.upstreams) {
+                (Some(forwarder), _) | (None, Upstreams::Forward { forwarder }) => {
+                    let socket = self.pool.get().await;
+                    let exchange = forwarder.forward(&socket, question, &options).await?;
+                    Ok(exchange.response)
+                }
+                (None, Upstreams::Iterate { servers }) => self.iterate(servers, question).await,
+            }
+        };
+        let result = timeout(RESOLVE_TIMEOUT, resolution)
+            .await
+            .map_err(|_| anyhow!("Gave up after {} seconds", RESOLVE_TIMEOUT.as_secs()))??;
+        if cacheable {
+            self.cache.insert(question.clone(), &result);
+        }
+        Ok(result)
+    }
+
+    /// The zone we are authoritative for that `qname` lies in, unless it is
+    /// below a deeper forward zone.
+    fn zone(&self, qname: &Name) -> Option<&Zone> {
+        self.closest_zone(qname)
+            .and_then(|origin| self.zones.get(origin))
+    }
+
+    /// The forwarder of the forward zone `qname` lies in, unless it is below
+    /// a deeper zone we are authoritative for.
+    fn forward_zone(&self, qname: &Name) -> Option<&Forwarder> {
+        self.closest_zone(qname)
+            .and_then(|zone| self.forward_zones.get(zone))
+    }
+
+    /// The deepest of the authoritative and forward zones that `qname` lies in.
+    fn closest_zone(&self, qname: &Name) -> Option<&Name> {
+        qname.closest_zone(self.zones.keys().chain(self.forward_zones.keys()))
+    }
+
+    /// The options to forward `request` with, which keep its CD flag and DO bit.
+    fn forward_options(&self, request: &DnsPacket) -> LookupOptions {
+        LookupOptions {
+            checking_disabled: request.header.checking_disabled,
+            dnssec_ok: request.edns.as_ref().is_some_and(|edns| edns.dnssec_ok),
+            ..self.options.clone()
+        }
+    }
+
+    async fn iterate(&self, servers: &[IpAddr], question: &DnsQuestion) -> Result<DnsPacket> {
+        let socket = self.pool.get().await;
+        let mut trace = Vec::new();
+        let result = traced_recursive_lookup(
+            &socket,
+            servers,
+            &question.qname,
+            question.qtype,
+            &self.options,
+            &mut trace,
+        )
+        .await;
+
+        for step in &trace {
+            let outcome = match &step.referral {
+                Some(zone) => format!("referral to {}", zone),
+                None => step.exchange.response.header.result_code.to_string(),
+            };
+            debug!(
+                "lookup of {} {} with ns {} took {} ms: {}",
+                step.qtype,
+                step.qname,
+                step.exchange.server,
+                step.exchange.elapsed.as_millis(),
+                outcome
+            );
+        }
+
+        result
+    }
+}
+
+/// The FORMERR response to a request that could not be parsed, or `None` if
+/// not even its header could be, or it is a response itself that must not be
+/// answered.
+pub fn format_error(message: &[u8]) -> Option<DnsPacket> {
+    let mut header = DnsHeader::new();
+    header
+        .read(&mut BytePacketBuffer::from_bytes(message))
+        .ok()?;
+    if header.response {
+        return None;
+    }
+
+    let mut packet = DnsPacket::new();
+    packet.header.id = header.id;
+    packet.header.opcode = header.opcode;
+    packet.header.recursion_desired = header.recursion_desired;
+    packet.header.recursion_available = true;
+    packet.header.response = true;
+    packet.header.result_code = ResultCode::FORMERR;
+    Some(packet)
+}
+
+/// The largest response we may send to `request` over UDP.
+pub fn udp_payload_size(request: &DnsPacket) -> usize {
+    match &request.edns {
+        // RFC 6891: never send more than the requester can take, and treat
+        // advertised sizes below 512 as 512.
+        Some(edns) => edns
+            .payload_size
+            .clamp(UDP_MESSAGE_SIZE as u16, EDNS_PAYLOAD_SIZE) as usize,
+        None => UDP_MESSAGE_SIZE,
+    }
+}
+
+/// Serialize `response` into at most `max_size` bytes.
+///
+/// If the records do not fit, they are dropped and the TC flag is set so that
+/// the client retries over TCP (RFC 2181 section 9).
+pub fn write_response(response: &mut DnsPacket, max_size: usize) -> Result<BytePacketBuffer> {
+    let mut buffer = BytePacketBuffer::with_max_size(max_size);
+    if response.write(&mut buffer).is_ok() {
+        return Ok(buffer);
+    }
+
+    response.header.truncated_message = true;
+    response.answers.clear();
+    response.authorities.clear();
+    response.resources.clear();
+
+    let mut buffer = BytePacketBuffer::with_max_size(max_size);
+    response.write(&mut buffer)?;
+    Ok(buffer)
+}
@@ -1,88 +1,291 @@
+use std::collections::HashMap;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
-use tokio::net::UdpSocket;
+use anyhow::{Context, Result};
+use clap::Parser;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
 use tub::Pool;
 
-use dns_common::{recursive_lookup, BytePacketBuffer, DnsPacket, ResultCode};
+use dns_common::{
+    dual_stack_socket, prime_root_servers, read_tcp_message, read_zone_file, root_servers,
+    write_tcp_message, BytePacketBuffer, DnsPacket, LookupOptions, MAX_MESSAGE_SIZE,
+    UDP_MESSAGE_SIZE,
+};
 
-// Default to 8.8.8.8:53 which is Google's DNS server
-// Alternatively we could use 1.1.1.1:53 which is Cloudflare's DNS server
-// Or some user-specific DNS server
-static GOOGLE_DNS: &str = "8.8.8.8";
+use crate::args::Args;
+use crate::cache::Cache;
+use crate::chaos::Identity;
+use crate::config::{Config, Mode, ZoneFile};
+use crate::forwarder::Forwarder;
+use crate::handler::{format_error, udp_payload_size, write_response, Handler, Upstreams};
+use crate::zone::Zone;
+
+mod args;
+mod cache;
+mod chaos;
+mod config;
+mod forwarder;
+mod handler;
+mod zone;
+
+/// Close TCP connections that send nothing for this long, so idle clients
+/// cannot tie up the server (RFC 7766 section 6.2.3).
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stop accepting TCP connections while this many are open.
+const MAX_TCP_CONNECTIONS: usize = 150;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let socket = UdpSocket::bind("0.0.0.0:8080").await?;
-    let pool = Arc::new(get_socket_pool().await?);
+    let config = Config::load(Args::parse())?;
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::from(config.log_level))
+        .init();
+
+    let options = LookupOptions {
+        timeout: Duration::from_secs(config.timeout),
+        retries: config.retries,
+        recursion_desired: false,
+        ..LookupOptions::default()
+    };
+
+    let mut zones = HashMap::new();
+    for zone_file in &config.zones {
+        let zone = load_zone(zone_file)?;
+        info!(
+            "Loaded zone {} with {} records",
+            zone.origin(),
+            zone.record_count()
+        );
+        zones.insert(zone.origin().clone(), zone);
+    }
+
+    let mut udp_sockets = vec![];
+    let mut tcp_listeners = vec![];
+    for listen in &config.listen {
+        if listen.protocol.udp() {
+            udp_sockets.push(bind_udp(listen.addr).await?);
+        }
+        if listen.protocol.tcp() {
+            tcp_listeners.push(bind_tcp(listen.addr).await?);
+        }
+    }
+
+    let pool = get_socket_pool(config.pool_size).await?;
+
+    let upstreams = match config.mode {
+        Mode::Forward => Upstreams::Forward {
+            forwarder: Forwarder::new(
+                config.upstreams.iter().map(|u| u.addr).collect(),
+                config.strategy,
+            ),
+        },
+        Mode::Iterate if !config.upstreams.is_empty() => Upstreams::Iterate {
+            servers: config.upstreams.iter().map(|u| u.addr.ip()).collect(),
+        },
+        Mode::Iterate if config.prime => {
+            let socket = pool.get().await;
+            let servers = prime_root_servers(&socket, &options)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to prime root servers, using root hints: {:#}", e);
+                    root_servers()
+                });
+            Upstreams::Iterate { servers }
+        }
+        Mode::Iterate => Upstreams::Iterate {
+            servers: root_servers(),
+        },
+    };
+
+    let forward_zones = config
+        .forward_zones
+        .into_iter()
+        .map(|forward_zone| {
+            let servers = forward_zone.upstreams.iter().map(|u| u.addr).collect();
+            (forward_zone.zone, Forwarder::new(servers, config.strategy))
+        })
+        .collect();
+
+    let cache = Cache::new(config.cache_size);
+    let identity = Identity {
+        version: config.server_version,
+        hostname: config.hostname,
+        id: config.server_id,
+    };
+    let handler = Arc::new(Handler::new(
+        pool,
+        upstreams,
+        forward_zones,
+        zones,
+        options,
+        cache,
+        identity,
+    ));
+
+    let mut servers = JoinSet::new();
+    for socket in udp_sockets {
+        servers.spawn(serve_udp(Arc::new(socket), handler.clone()));
+    }
+    for listener in tcp_listeners {
+        servers.spawn(serve_tcp(listener, handler.clone()));
+    }
+
+    // The servers only return on errors, which end the process.
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
+
+fn load_zone(zone_file: &ZoneFile) -> Result<Zone> {
+    let ZoneFile { origin, path } = zone_file;
+    read_zone_file(path, origin)
+        .and_then(|records| Zone::new(origin.clone(), records))
+        .with_context(|| format!("Failed to load zone {} from {}", origin, path.display()))
+}
+
+/// Bind a UDP socket to `addr`.
+///
+/// On hosts without IPv6, listening on all IPv6 addresses falls back to all
+/// IPv4 addresses, which dual-stack hosts would have accepted anyway.
+async fn bind_udp(addr: SocketAddr) -> Result<UdpSocket> {
+    let socket = match UdpSocket::bind(addr).await {
+        Err(_) if is_unspecified_v6(addr) => UdpSocket::bind(unspecified_v4(addr)).await,
+        result => result,
+    };
+    let socket = socket.with_context(|| format!("Failed to listen on {} over UDP", addr))?;
+
+    info!("Listening on {} over UDP", socket.local_addr()?);
+    Ok(socket)
+}
+
+/// Bind a TCP listener to `addr`, with the same fallback as `bind_udp`.
+async fn bind_tcp(addr: SocketAddr) -> Result<TcpListener> {
+    let listener = match TcpListener::bind(addr).await {
+        Err(_) if is_unspecified_v6(addr) => TcpListener::bind(unspecified_v4(addr)).await,
+        result => result,
+    };
+    let listener = listener.with_context(|| format!("Failed to listen on {} over TCP", addr))?;
+
+    info!("Listening on {} over TCP", listener.local_addr()?);
+    Ok(listener)
+}
+
+fn is_unspecified_v6(addr: SocketAddr) -> bool {
+    addr.is_ipv6() && addr.ip().is_unspecified()
+}
+
+fn unspecified_v4(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), addr.port())
+}
+
+async fn serve_udp(socket: Arc<UdpSocket>, handler: Arc<Handler>) -> Result<()> {
+    let mut buf = vec![0; MAX_MESSAGE_SIZE];
+
     loop {
-        let pool = pool.clone();
-        let (src, buffer) = get_request(&socket).await?;
+        // Copy out just the message, so that requests waiting on upstreams do
+        // not each hold on to a buffer of the largest possible size.
+        let (len, src) = socket.recv_from(&mut buf).await?;
+        let message = buf[..len].to_vec();
+
+        let socket = socket.clone();
+        let handler = handler.clone();
         tokio::spawn(async move {
-            send_response(pool, src, buffer).await.unwrap();
+            if let Err(e) = send_udp_response(&socket, &handler, src, &message).await {
+                warn!("Failed to answer {} over UDP: {:#}", src, e);
+            }
         });
     }
 }
 
-async fn get_request(socket: &UdpSocket) -> Result<(SocketAddr, BytePacketBuffer)> {
-    let mut buf = BytePacketBuffer::new();
-    let (_, src) = socket.recv_from(&mut buf.buffer).await?;
-    let mut request = DnsPacket::from_buffer(&mut buf)?;
-
-    let mut packet = DnsPacket::new();
-    packet.header.id = request.header.id;
-    packet.header.recursion_desired = true;
-    packet.header.recursion_available = true;
-    packet.header.response = true;
-
-    if let Some(question) = request.questions.pop() {
-        let dns = GOOGLE_DNS.parse::<Ipv4Addr>()?;
-        if let Ok(result) = recursive_lookup(socket, dns, &question.qname, question.qtype).await {
-            packet.questions.push(question);
-            packet.header.result_code = result.header.result_code;
-
-            for rec in result.answers {
-                println!("Answer: {:?}", rec);
-                packet.answers.push(rec);
-            }
+async fn send_udp_response(
+    socket: &UdpSocket,
+    handler: &Handler,
+    src: SocketAddr,
+    message: &[u8],
+) -> Result<()> {
+    let request = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(message));
+    let (mut response, max_size) = match request {
+        // Answering responses would let two servers bounce them back and forth.
+        Ok(request) if request.header.response => {
+            debug!("Dropping a response from {}", src);
+            return Ok(());
+        }
+        Ok(request) => {
+            let max_size = udp_payload_size(&request);
+            (handler.handle_request(request).await, max_size)
+        }
+        Err(e) => {
+            debug!("Malformed request from {}: {:#}", src, e);
+            (format_error(message).ok_or(e)?, UDP_MESSAGE_SIZE)
+        }
+    };
 
-            for rec in result.authorities {
-                println!("Authority: {:?}", rec);
-                packet.authorities.push(rec);
-            }
+    let buffer = write_response(&mut response, max_size)?;
+    socket.send_to(buffer.as_bytes(), src).await?;
 
-            for rec in result.resources {
-                println!("Resource: {:?}", rec);
-                packet.resources.push(rec);
-            }
-        } else {
-            packet.header.result_code = ResultCode::SERVFAIL;
-        }
-    } else {
-        packet.header.result_code = ResultCode::FORMERR;
-    }
+    Ok(())
+}
+
+async fn serve_tcp(listener: TcpListener, handler: Arc<Handler>) -> Result<()> {
+    let connections = Arc::new(Semaphore::new(MAX_TCP_CONNECTIONS));
 
-    let mut res_buffer = BytePacketBuffer::new();
-    packet.write(&mut res_buffer)?;
+    loop {
+        // At the limit, new connections wait in the listen backlog until an
+        // open one closes.
+        let permit = connections.clone().acquire_owned().await?;
+        let (stream, src) = listener.accept().await?;
 
-    Ok((src, res_buffer))
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_tcp_connection(stream, src, &handler).await {
+                warn!("Failed to answer {} over TCP: {:#}", src, e);
+            }
+            drop(permit);
+        });
+    }
 }
 
-pub async fn send_response(
-    pool: Arc<Pool<UdpSocket>>,
+/// Answer requests on a TCP connection until the client closes it or stays
+/// idle for `TCP_IDLE_TIMEOUT`.
+async fn serve_tcp_connection(
+    mut stream: TcpStream,
     src: SocketAddr,
-    buffer: BytePacketBuffer,
+    handler: &Handler,
 ) -> Result<()> {
-    pool.get().await.send_to(&buffer.buffer, src).await?;
+    while let Ok(Ok(message)) = timeout(TCP_IDLE_TIMEOUT, read_tcp_message(&mut stream)).await {
+        let request = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&message));
+        let mut response = match request {
+            Ok(request) if request.header.response => {
+                debug!("Dropping a response from {}", src);
+                continue;
+            }
+            Ok(request) => handler.handle_request(request).await,
+            Err(e) => {
+                debug!("Malformed request from {}: {:#}", src, e);
+                format_error(&message).ok_or(e)?
+            }
+        };
+        let buffer = write_response(&mut response, MAX_MESSAGE_SIZE)?;
+        write_tcp_message(&mut stream, buffer.as_bytes()).await?;
+    }
+
     Ok(())
 }
 
-pub async fn get_socket_pool() -> Result<Pool<UdpSocket>> {
+pub async fn get_socket_pool(size: usize) -> Result<Pool<UdpSocket>> {
     let mut sockets = vec![];
 
-    for _ in 0..10 {
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    for _ in 0..size {
+        let socket = dual_stack_socket(0).await?;
         sockets.push(socket);
     }
 
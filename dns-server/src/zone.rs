@@ -0,0 +1,483 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+
+use dns_common::{DnsPacket, DnsQuestion, DnsRecord, Name, QueryType, ResultCode, MAX_CNAME_CHAIN};
+
+/// A zone the server is authoritative for.
+pub struct Zone {
+    origin: Name,
+    soa: DnsRecord,
+    /// The records of every name that has any.
+    records: HashMap<Name, Vec<DnsRecord>>,
+    /// Every name that exists in the zone, including the empty non-terminals
+    /// between the names that own records and the origin.
+    names: HashSet<Name>,
+}
+
+impl Zone {
+    /// Build a zone from its records, which must include an SOA at `origin`.
+    pub fn new(origin: Name, records: Vec<DnsRecord>) -> Result<Zone> {
+        let mut soa = None;
+        let mut by_name: HashMap<Name, Vec<DnsRecord>> = HashMap::new();
+
+        for record in records {
+            let owner = record.domain();
+            if !owner.is_subdomain_of(&origin) {
+                return Err(anyhow!("{} is outside of zone {}", owner, origin));
+            }
+            if record.query_type() == QueryType::SOA {
+                if *owner != origin {
+                    return Err(anyhow!("SOA record {} is not at the zone apex", owner));
+                }
+                if soa.is_some() {
+                    return Err(anyhow!("More than one SOA record"));
+                }
+                soa = Some(record.clone());
+            }
+
+            by_name.entry(owner.clone()).or_default().push(record);
+        }
+
+        let soa = soa.ok_or_else(|| anyhow!("Missing SOA record at {}", origin))?;
+
+        for (name, records) in &by_name {
+            let has_cname = records.iter().any(|r| r.query_type() == QueryType::CNAME);
+            if has_cname && records.len() > 1 {
+                return Err(anyhow!("{} has a CNAME record and other data", name));
+            }
+        }
+
+        let names = by_name
+            .keys()
+            .flat_map(|name| {
+                name.ancestors()
+                    .take_while(|ancestor| ancestor.is_subdomain_of(&origin))
+            })
+            .collect();
+
+        Ok(Zone {
+            origin,
+            soa,
+            records: by_name,
+            names,
+        })
+    }
+
+    pub fn origin(&self) -> &Name {
+        &self.origin
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.values().map(Vec::len).sum()
+    }
+
+    /// Answer a question for a name in the zone (RFC 1034 section 4.3.2).
+    ///
+    /// Names at or below a zone cut get a referral. Everything else gets an
+    /// authoritative answer, possibly synthesized from a wildcard (RFC 4592),
+    /// or NXDOMAIN or NODATA with the SOA in the authority section. CNAME
+    /// loops and overly long chains within the zone get SERVFAIL.
+    pub fn answer(&self, question: &DnsQuestion, packet: &mut DnsPacket) {
+        packet.header.result_code = ResultCode::NOERROR;
+        let mut qname = question.qname.clone();
+        let mut chain = vec![qname.clone()];
+
+        loop {
+            // A CNAME that leads out of the zone ends the answer; the
+            // resolver follows it from there.
+            if !qname.is_subdomain_of(&self.origin) {
+                return;
+            }
+
+            if let Some(cut) = self.zone_cut(&qname, question.qtype) {
+                self.refer(&cut, packet);
+                return;
+            }
+            packet.header.authoritative_answer = true;
+
+            let records = match self.records.get(&qname) {
+                Some(records) => records.clone(),
+                // An empty non-terminal exists, but has no data.
+                None if self.names.contains(&qname) => Vec::new(),
+                None => match self.wildcard(&qname) {
+                    Some(records) => records,
+                    None => {
+                        packet.header.result_code = ResultCode::NXDOMAIN;
+                        packet.authorities.push(self.negative_soa());
+                        return;
+                    }
+                },
+            };
+
+            if !matches!(question.qtype, QueryType::CNAME | QueryType::ANY) {
+                let cname = records.iter().find(|r| r.query_type() == QueryType::CNAME);
+                if let Some(cname @ DnsRecord::CNAME { alias, .. }) = cname {
+                    if chain.contains(alias) || chain.len() > MAX_CNAME_CHAIN {
+                        packet.header.result_code = ResultCode::SERVFAIL;
+                        packet.header.authoritative_answer = false;
+                        packet.answers.clear();
+                        return;
+                    }
+                    qname = alias.clone();
+                    chain.push(qname.clone());
+                    packet.answers.push(cname.clone());
+                    continue;
+                }
+            }
+
+            let answers: Vec<DnsRecord> = records
+                .into_iter()
+                .filter(|r| question.qtype == QueryType::ANY || r.query_type() == question.qtype)
+                .collect();
+            if answers.is_empty() {
+                packet.authorities.push(self.negative_soa());
+                return;
+            }
+
+            packet.resources.extend(self.additional(&answers));
+            packet.answers.extend(answers);
+            return;
+        }
+    }
+
+    /// The highest name with NS records between the origin (exclusive) and
+    /// `qname` (inclusive), if any. Below it, the zone only holds glue.
+    fn zone_cut(&self, qname: &Name, qtype: QueryType) -> Option<Name> {
+        let mut ancestors: Vec<Name> = qname
+            .ancestors()
+            .take_while(|name| *name != self.origin)
+            .collect();
+        ancestors.reverse();
+
+        ancestors.into_iter().find(|name| {
+            // The parent side of the cut answers for DS records.
+            if name == qname && qtype == QueryType::DS {
+                return false;
+            }
+            self.records
+                .get(name)
+                .is_some_and(|records| records.iter().any(|r| r.query_type() == QueryType::NS))
+        })
+    }
+
+    /// Refer the client to the name servers of the child zone at `cut`,
+    /// along with the glue addresses we hold for them.
+    fn refer(&self, cut: &Name, packet: &mut DnsPacket) {
+        let ns: Vec<DnsRecord> = self.records[cut]
+            .iter()
+            .filter(|r| r.query_type() == QueryType::NS)
+            .cloned()
+            .collect();
+
+        packet.resources.extend(self.additional(&ns));
+        packet.authorities.extend(ns);
+    }
+
+    /// The records of the wildcard that `qname` matches, with `qname` as
+    /// their owner, or `None` if there is no such wildcard (RFC 4592).
+    ///
+    /// The wildcard is the `*` child of the closest encloser, the deepest
+    /// existing ancestor of `qname`. A wildcard without records of its own
+    /// still matches, and yields NODATA.
+    fn wildcard(&self, qname: &Name) -> Option<Vec<DnsRecord>> {
+        let encloser = qname
+            .ancestors()
+            .skip(1)
+            .find(|name| self.names.contains(name))?;
+        let source = encloser.child("*").ok()?;
+        if !self.names.contains(&source) {
+            return None;
+        }
+
+        let records = self.records.get(&source).cloned().unwrap_or_default();
+        Some(
+            records
+                .into_iter()
+                .map(|mut record| {
+                    record.set_domain(qname.clone());
+                    record
+                })
+                .collect(),
+        )
+    }
+
+    /// The addresses we hold for the hosts named by NS, MX and SRV records.
+    fn additional(&self, records: &[DnsRecord]) -> Vec<DnsRecord> {
+        let mut additional: Vec<DnsRecord> = Vec::new();
+
+        for record in records {
+            let host = match record {
+                DnsRecord::NS { name_server, .. } => name_server,
+                DnsRecord::MX { host, .. } => host,
+                DnsRecord::SRV { target, .. } => target,
+                _ => continue,
+            };
+
+            let addresses = self
+                .records
+                .get(host)
+                .into_iter()
+                .flatten()
+                .filter(|r| matches!(r.query_type(), QueryType::A | QueryType::AAAA));
+            for address in addresses {
+                if !additional.contains(address) {
+                    additional.push(address.clone());
+                }
+            }
+        }
+
+        additional
+    }
+
+    /// The SOA to put in the authority section of negative answers, whose TTL
+    /// caps how long they are cached (RFC 2308 section 3).
+    fn negative_soa(&self) -> DnsRecord {
+        let mut soa = self.soa.clone();
+        if let DnsRecord::SOA { minimum, ttl, .. } = &soa {
+            let negative_ttl = (*minimum).min(*ttl);
+            soa.set_ttl(negative_ttl);
+        }
+        soa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use dns_common::{parse_zone, DnsClass};
+
+    use super::*;
+
+    const ZONE: &str = "\
+$TTL 3600
+@         SOA ns1 hostmaster 1 7200 900 604800 300
+          NS ns1
+ns1       A 192.0.2.53
+www       CNAME web
+web       CNAME host.a.b
+host.a.b  A 192.0.2.1
+out       CNAME www.example.org.
+*.wild    A 192.0.2.2
+x.wild    A 192.0.2.3
+sub       NS ns.sub
+ns.sub    A 192.0.2.54
+";
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn zone() -> Zone {
+        let origin = name("example.com");
+        Zone::new(origin.clone(), parse_zone(ZONE, &origin).unwrap()).unwrap()
+    }
+
+    fn answer(qname: &str, qtype: QueryType) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        zone().answer(&DnsQuestion::new(name(qname), qtype), &mut packet);
+        packet
+    }
+
+    fn a(domain: &str, address: Ipv4Addr) -> DnsRecord {
+        DnsRecord::A {
+            domain: name(domain),
+            address,
+            class: DnsClass::IN,
+            ttl: 3600,
+        }
+    }
+
+    fn cname(domain: &str, alias: &str) -> DnsRecord {
+        DnsRecord::CNAME {
+            domain: name(domain),
+            alias: name(alias),
+            class: DnsClass::IN,
+            ttl: 3600,
+        }
+    }
+
+    /// Whether `packet` is a negative answer with the SOA, its TTL capped at
+    /// the SOA minimum.
+    fn is_negative(packet: &DnsPacket) -> bool {
+        packet.answers.is_empty()
+            && matches!(
+                packet.authorities.as_slice(),
+                [soa @ DnsRecord::SOA { .. }] if soa.ttl() == 300
+            )
+    }
+
+    #[test]
+    fn apex_ns_is_answered_with_glue() {
+        let packet = answer("example.com", QueryType::NS);
+        assert!(packet.header.authoritative_answer);
+        assert_eq!(packet.answers.len(), 1);
+        assert_eq!(
+            packet.resources,
+            vec![a("ns1.example.com", Ipv4Addr::new(192, 0, 2, 53))]
+        );
+    }
+
+    #[test]
+    fn cname_chain_within_the_zone_is_followed() {
+        let packet = answer("www.example.com", QueryType::A);
+        assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+        assert!(packet.header.authoritative_answer);
+        assert_eq!(
+            packet.answers,
+            vec![
+                cname("www.example.com", "web.example.com"),
+                cname("web.example.com", "host.a.b.example.com"),
+                a("host.a.b.example.com", Ipv4Addr::new(192, 0, 2, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn cname_loop_within_the_zone_is_servfail() {
+        let origin = name("example.com");
+        let text = "$TTL 300\n@ SOA ns1 hostmaster 1 2 3 4 5\na CNAME b\nb CNAME a\n";
+        let zone = Zone::new(origin.clone(), parse_zone(text, &origin).unwrap()).unwrap();
+
+        let mut packet = DnsPacket::new();
+        zone.answer(
+            &DnsQuestion::new(name("a.example.com"), QueryType::A),
+            &mut packet,
+        );
+        assert_eq!(packet.header.result_code, ResultCode::SERVFAIL);
+        assert!(packet.answers.is_empty());
+    }
+
+    #[test]
+    fn cname_leaving_the_zone_ends_the_answer() {
+        let packet = answer("out.example.com", QueryType::A);
+        assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+        assert_eq!(
+            packet.answers,
+            vec![cname("out.example.com", "www.example.org")]
+        );
+        assert!(packet.authorities.is_empty());
+    }
+
+    #[test]
+    fn cname_is_returned_itself_when_asked_for() {
+        let packet = answer("www.example.com", QueryType::CNAME);
+        assert_eq!(
+            packet.answers,
+            vec![cname("www.example.com", "web.example.com")]
+        );
+    }
+
+    #[test]
+    fn empty_non_terminal_is_nodata() {
+        for qname in ["a.b.example.com", "b.example.com"] {
+            let packet = answer(qname, QueryType::A);
+            assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+            assert!(is_negative(&packet));
+        }
+    }
+
+    #[test]
+    fn missing_name_is_nxdomain() {
+        let packet = answer("missing.example.com", QueryType::A);
+        assert_eq!(packet.header.result_code, ResultCode::NXDOMAIN);
+        assert!(packet.header.authoritative_answer);
+        assert!(is_negative(&packet));
+    }
+
+    #[test]
+    fn missing_type_is_nodata() {
+        let packet = answer("ns1.example.com", QueryType::AAAA);
+        assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+        assert!(is_negative(&packet));
+    }
+
+    #[test]
+    fn wildcard_is_synthesized_with_the_query_name() {
+        let packet = answer("foo.wild.example.com", QueryType::A);
+        assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+        assert_eq!(
+            packet.answers,
+            vec![a("foo.wild.example.com", Ipv4Addr::new(192, 0, 2, 2))]
+        );
+
+        let packet = answer("foo.wild.example.com", QueryType::MX);
+        assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+        assert!(is_negative(&packet));
+    }
+
+    #[test]
+    fn wildcard_does_not_cover_existing_names_or_their_children() {
+        // x.wild exists, so it answers for itself, and names below it are
+        // not covered by *.wild, whose closest encloser is x.wild instead.
+        let packet = answer("x.wild.example.com", QueryType::A);
+        assert_eq!(
+            packet.answers,
+            vec![a("x.wild.example.com", Ipv4Addr::new(192, 0, 2, 3))]
+        );
+
+        let packet = answer("y.x.wild.example.com", QueryType::A);
+        assert_eq!(packet.header.result_code, ResultCode::NXDOMAIN);
+    }
+
+    #[test]
+    fn names_at_and_below_a_zone_cut_are_referred() {
+        for qname in [
+            "sub.example.com",
+            "host.sub.example.com",
+            "ns.sub.example.com",
+        ] {
+            let packet = answer(qname, QueryType::A);
+            assert_eq!(packet.header.result_code, ResultCode::NOERROR);
+            assert!(!packet.header.authoritative_answer);
+            assert!(packet.answers.is_empty());
+            assert_eq!(
+                packet.authorities,
+                vec![DnsRecord::NS {
+                    domain: name("sub.example.com"),
+                    name_server: name("ns.sub.example.com"),
+                    class: DnsClass::IN,
+                    ttl: 3600,
+                }]
+            );
+            assert_eq!(
+                packet.resources,
+                vec![a("ns.sub.example.com", Ipv4Addr::new(192, 0, 2, 54))]
+            );
+        }
+    }
+
+    #[test]
+    fn ds_at_a_zone_cut_is_answered_by_the_parent() {
+        let packet = answer("sub.example.com", QueryType::DS);
+        assert!(packet.header.authoritative_answer);
+        assert!(is_negative(&packet));
+    }
+
+    #[test]
+    fn new_rejects_invalid_zones() {
+        let origin = name("example.com");
+        let cases = [
+            ("www A 192.0.2.1\n", "Missing SOA record"),
+            (
+                "@ SOA ns1 hostmaster 1 2 3 4 5\nwww.example.org. A 192.0.2.1\n",
+                "outside of zone",
+            ),
+            (
+                "@ SOA ns1 hostmaster 1 2 3 4 5\nwww SOA ns1 hostmaster 1 2 3 4 5\n",
+                "not at the zone apex",
+            ),
+            (
+                "@ SOA ns1 hostmaster 1 2 3 4 5\nwww CNAME web\nwww A 192.0.2.1\n",
+                "CNAME record and other data",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let records = parse_zone(&format!("$TTL 300\n{}", text), &origin).unwrap();
+            let err = Zone::new(origin.clone(), records).err().unwrap();
+            assert!(err.to_string().contains(expected), "{:?}: {}", text, err);
+        }
+    }
+}